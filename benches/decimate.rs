@@ -0,0 +1,86 @@
+//! Draw time of a graph series with all points against the decimated points.
+//! Run with `cargo bench --bench decimate`.
+
+#[path = "../src/widgets/graph/decimate.rs"]
+mod decimate;
+
+use std::time::{Duration, Instant};
+
+/// Size of the drawing surface in pixels, about the size of the graph in the window
+const WIDTH: i32 = 800;
+const HEIGHT: i32 = 300;
+
+/// Repetitions of each measurement. The fastest run is reported.
+const RUNS: usize = 5;
+
+/// A noisy sine with a spike every 997 points, sampled at 1 kHz
+fn series(count: usize) -> Vec<(f64, f64)> {
+    (0..count)
+        .map(|i| {
+            let x = i as f64 * 0.001;
+            let spike = if i % 997 == 0 { 80.0 } else { 0.0 };
+            (x, 20.0 * (x * 3.0).sin() + (i as f64 * 7.3).sin() + spike)
+        })
+        .collect()
+}
+
+fn draw(cx: &cairo::Context, points: &[(f64, f64)], x_max: f64) {
+    let x_scaling = f64::from(WIDTH) / x_max;
+    let y_scaling = f64::from(HEIGHT) / 200.0;
+    cx.set_source_rgb(0.0, 0.0, 0.0);
+    cx.paint();
+    cx.set_line_width(2.0);
+    cx.set_source_rgb(1.0, 0.0, 0.0);
+    for (index, &(x, y)) in points.iter().enumerate() {
+        let x = x * x_scaling;
+        let y = f64::from(HEIGHT) / 2.0 - y * y_scaling;
+        if index == 0 {
+            cx.move_to(x, y);
+        } else {
+            cx.line_to(x, y);
+        }
+    }
+    cx.stroke();
+}
+
+/// Fastest time of `RUNS` runs
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, WIDTH, HEIGHT).unwrap();
+    let cx = cairo::Context::new(&surface);
+
+    println!(
+        "{:>10} {:>10} {:>14} {:>14}",
+        "points", "drawn", "all [ms]", "decimated [ms]"
+    );
+    for &count in [1_000, 10_000, 100_000, 1_000_000].iter() {
+        let points = series(count);
+        let x_max = points.last().unwrap().0;
+
+        let all = measure(|| draw(&cx, &points, x_max));
+        let mut drawn = 0;
+        let decimated = measure(|| {
+            let reduced =
+                decimate::min_max_decimate(points.iter().cloned(), 0.0, x_max, WIDTH as usize);
+            drawn = reduced.len();
+            draw(&cx, &reduced, x_max);
+        });
+        println!(
+            "{:>10} {:>10} {:>14.3} {:>14.3}",
+            count,
+            drawn,
+            all.as_secs_f64() * 1000.0,
+            decimated.as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Virtual gamepad for testing the joystick input without hardware.
+//!
+//! Creates a uinput device with the axes of the default mapping and three buttons,
+//! then moves one axis after another and presses the buttons.
+//!
+//! 1. Run `cargo run --example virtual_joystick`. Write access to `/dev/uinput` is needed,
+//!    e.g. run as root or add a udev rule for your user.
+//! 2. Press "Refresh" in the "Joystick" frame and select "Virtual Joystick".
+//! 3. Bind BTN_SOUTH to Arm, BTN_EAST to Disarm and BTN_NORTH to Mode Switch, then press "Enable".
+//! 4. Press Enter in the terminal. Each step is printed, so the setpoints can be compared with it.
+
+use evdev::uinput::VirtualDeviceBuilder;
+use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, UinputAbsSetup};
+
+/// Range of the axes, like most gamepads report it
+const AXIS_MIN: i32 = -32768;
+const AXIS_MAX: i32 = 32767;
+
+/// Axes in the order they are moved. Throttle is inverted in the default mapping.
+const AXES: [(&str, AbsoluteAxisType); 4] = [
+    ("Throttle", AbsoluteAxisType::ABS_Y),
+    ("Roll", AbsoluteAxisType::ABS_RX),
+    ("Pitch", AbsoluteAxisType::ABS_RY),
+    ("Yaw", AbsoluteAxisType::ABS_X),
+];
+
+/// Buttons with the action they should be bound to
+const BUTTONS: [(&str, Key); 3] = [
+    ("Arm", Key::BTN_SOUTH),
+    ("Disarm", Key::BTN_EAST),
+    ("Mode Switch", Key::BTN_NORTH),
+];
+
+/// Time between two events of a sweep
+const STEP: std::time::Duration = std::time::Duration::from_millis(10);
+/// Number of events of a sweep from one end to the other
+const SWEEP_STEPS: i32 = 200;
+
+fn axis_event(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
+
+/// Move an axis linearly from one value to another
+fn sweep(
+    device: &mut evdev::uinput::VirtualDevice,
+    axis: AbsoluteAxisType,
+    from: i32,
+    to: i32,
+) -> std::io::Result<()> {
+    for step in 0..=SWEEP_STEPS {
+        let value = from + ((to - from) as i64 * step as i64 / SWEEP_STEPS as i64) as i32;
+        device.emit(&[axis_event(axis, value)])?;
+        std::thread::sleep(STEP);
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let mut keys = AttributeSet::<Key>::new();
+    for &(_, key) in BUTTONS.iter() {
+        keys.insert(key);
+    }
+    let mut builder = VirtualDeviceBuilder::new()?
+        .name("Virtual Joystick")
+        .with_keys(&keys)?;
+    for &(_, axis) in AXES.iter() {
+        let info = AbsInfo::new(0, AXIS_MIN, AXIS_MAX, 0, 0, 0);
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, info))?;
+    }
+    let mut device = builder.build()?;
+
+    println!("Virtual Joystick created. Enable it in the application and press Enter to start.");
+    std::io::stdin().read_line(&mut String::new())?;
+
+    // Centered sticks and zero throttle
+    let mut center: Vec<InputEvent> = AXES.iter().map(|&(_, axis)| axis_event(axis, 0)).collect();
+    center[0] = axis_event(AbsoluteAxisType::ABS_Y, AXIS_MAX);
+    device.emit(&center)?;
+
+    loop {
+        for &(name, axis) in AXES.iter() {
+            let path: &[i32] = if axis == AbsoluteAxisType::ABS_Y {
+                &[AXIS_MAX, AXIS_MIN, AXIS_MAX]
+            } else {
+                &[0, AXIS_MIN, AXIS_MAX, 0]
+            };
+            println!("{}: full range and back", name);
+            for values in path.windows(2) {
+                sweep(&mut device, axis, values[0], values[1])?;
+            }
+        }
+        for &(action, key) in BUTTONS.iter() {
+            println!("{:?} ({})", key, action);
+            device.emit(&[InputEvent::new(EventType::KEY, key.code(), 1)])?;
+            std::thread::sleep(STEP * 10);
+            device.emit(&[InputEvent::new(EventType::KEY, key.code(), 0)])?;
+            std::thread::sleep(STEP * 100);
+        }
+    }
+}
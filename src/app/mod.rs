@@ -11,9 +11,16 @@ use crate::widgets;
 
 pub struct Model {}
 
+/// Keys that trigger the emergency stop
+fn is_emergency_stop_key(key: &gdk::keys::Key) -> bool {
+    *key == gdk::keys::constants::space || *key == gdk::keys::constants::Escape
+}
+
 #[derive(Msg)]
 pub enum Message {
     Quit,
+    Exit,
+    EmergencyStop,
 }
 
 pub struct App {
@@ -21,6 +28,17 @@ pub struct App {
     _graph: relm::Component<widgets::graph::Widget>,
     _connection: relm::Component<widgets::connection::Widget>,
     _control: relm::Component<widgets::control::Widget>,
+    _joystick: relm::Component<widgets::joystick::Widget>,
+    _keyboard: relm::Component<widgets::keyboard::Widget>,
+    _limiter: relm::Component<widgets::limiter::Widget>,
+    _sequence: relm::Component<widgets::sequence::Widget>,
+    _failsafe: relm::Component<widgets::failsafe::Widget>,
+    _experiment: relm::Component<widgets::experiment::Widget>,
+    _sweep: relm::Component<widgets::sweep::Widget>,
+    _bode: relm::Component<widgets::bode::Widget>,
+    _autotune: relm::Component<widgets::autotune::Widget>,
+    _trim: relm::Component<widgets::trim::Widget>,
+    _signals: relm::Channel<Message>,
     _model: Model,
 }
 
@@ -35,7 +53,13 @@ impl Update for App {
 
     fn update(&mut self, event: Self::Msg) {
         match event {
-            Message::Quit => gtk::main_quit(),
+            // Disarm and close the connection before the app ends
+            Message::Quit => self
+                ._connection
+                .emit(widgets::connection::Message::Shutdown),
+            Message::Exit => gtk::main_quit(),
+            // The connection is already stopped by the key handler, the control only follows
+            Message::EmergencyStop => self._control.emit(widgets::control::Message::EmergencyStop),
         }
     }
 }
@@ -58,6 +82,16 @@ impl Widget for App {
         let _connection = control_box.add_widget::<widgets::connection::Widget>(builder);
         let _graph = graph_box.add_widget::<widgets::graph::Widget>(());
         let _control = control_box.add_widget::<widgets::control::Widget>(());
+        let _limiter = control_box.add_widget::<widgets::limiter::Widget>(());
+        let _trim = control_box.add_widget::<widgets::trim::Widget>(());
+        let _failsafe = control_box.add_widget::<widgets::failsafe::Widget>(());
+        let _joystick = control_box.add_widget::<widgets::joystick::Widget>(());
+        let _keyboard = control_box.add_widget::<widgets::keyboard::Widget>(window.clone());
+        let _sequence = control_box.add_widget::<widgets::sequence::Widget>(());
+        let _experiment = control_box.add_widget::<widgets::experiment::Widget>(());
+        let _sweep = control_box.add_widget::<widgets::sweep::Widget>(());
+        let _autotune = control_box.add_widget::<widgets::autotune::Widget>(());
+        let _bode = graph_box.add_widget::<widgets::bode::Widget>(());
         graph_box.set_child_expand(&graph_box.get_children()[0], true);
 
         window.show_all();
@@ -70,11 +104,85 @@ impl Widget for App {
             return (Some(Message::Quit), Inhibit(false))
         );
 
-        // New data from device
+        // Close app on SIGINT and SIGTERM
+        let stream = relm.stream().clone();
+        let (_signals, signal_sender) = relm::Channel::new(move |msg| stream.emit(msg));
+        for &signal in [libc::SIGINT, libc::SIGTERM].iter() {
+            let signal_sender = signal_sender.clone();
+            glib::unix_signal_add(signal, move || {
+                signal_sender.send(Message::Quit).ok();
+                glib::Continue(true)
+            });
+        }
+        connect!(
+            _connection@widgets::connection::Message::ShutdownComplete,
+            relm,
+            Message::Exit
+        );
+
+        // Emergency stop works independent of the focused widget.
+        // The connection is stopped directly, so a busy control can't delay the stop.
+        {
+            let stream = relm.stream().clone();
+            let connection = _connection.stream().clone();
+            window.connect_key_press_event(move |_, key| {
+                if is_emergency_stop_key(&key.get_keyval()) {
+                    connection.emit(widgets::connection::Message::EmergencyStop);
+                    stream.emit(Message::EmergencyStop);
+                    Inhibit(true)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+
+        // New data from device. The level trims are removed first.
         connect!(
             _connection@widgets::connection::Message::RecivedAttitude(ref data),
+            _trim,
+            widgets::trim::Message::Attitude(data.timestamp, data.roll, data.pitch, data.yaw)
+        );
+        connect!(
+            _trim@widgets::trim::Message::TrimmedAttitude(ref timestamp, ref roll, ref pitch, ref yaw),
             _graph,
-            widgets::graph::Message::AddAngle(data.timestamp, data.roll, data.pitch, data.yaw)
+            widgets::graph::Message::AddAngle(*timestamp, *roll, *pitch, *yaw)
+        );
+        // Telemetry for the arming checks
+        connect!(
+            _trim@widgets::trim::Message::TrimmedAttitude(_, ref roll, ref pitch, _),
+            _control,
+            widgets::control::Message::Attitude(*roll, *pitch)
+        );
+        // Tilt failsafe while the motors are armed
+        connect!(
+            _trim@widgets::trim::Message::TrimmedAttitude(_, ref roll, ref pitch, _),
+            _failsafe,
+            widgets::failsafe::Message::Attitude(*roll, *pitch)
+        );
+        connect!(
+            _control@widgets::control::Message::EnableMotor,
+            _failsafe,
+            widgets::failsafe::Message::Armed(true)
+        );
+        connect!(
+            _control@widgets::control::Message::DisableMotor,
+            _failsafe,
+            widgets::failsafe::Message::Armed(false)
+        );
+        connect!(
+            _control@widgets::control::Message::EmergencyStop,
+            _failsafe,
+            widgets::failsafe::Message::Armed(false)
+        );
+        connect!(
+            _failsafe@widgets::failsafe::Message::Trip,
+            _control,
+            widgets::control::Message::FailsafeTrip
+        );
+        connect!(
+            _failsafe@widgets::failsafe::Message::Acknowledged,
+            _control,
+            widgets::control::Message::FailsafeAcknowledged
         );
         // Clear on new connect
         connect!(
@@ -88,17 +196,217 @@ impl Widget for App {
             _connection,
             widgets::connection::Message::SendMessage(copter_com::Message::EnableMotor)
         );
+        // Emergency Stop of the control button. A repeated stop after the key handler does no harm.
+        connect!(
+            _control@widgets::control::Message::EmergencyStop,
+            _connection,
+            widgets::connection::Message::EmergencyStop
+        );
         // Disable Motors
         connect!(
             _control@widgets::control::Message::DisableMotor,
             _connection,
             widgets::connection::Message::SendMessage(copter_com::Message::DisableMotor)
         );
-        // Send Setpoint
+        // Stream Setpoint through the trims and then the limiter, so the limits hold for the trimmed angles
         connect!(
             _control@widgets::control::Message::SendSetPoint(ref setpoint),
+            _trim,
+            widgets::trim::Message::SetPoint(Some(*setpoint))
+        );
+        connect!(
+            _control@widgets::control::Message::StopStream,
+            _trim,
+            widgets::trim::Message::SetPoint(None)
+        );
+        connect!(
+            _control@widgets::control::Message::SetSlewLimit(ref slew),
+            _limiter,
+            widgets::limiter::Message::SetSlewLimit(*slew)
+        );
+        connect!(
+            _trim@widgets::trim::Message::Output(ref setpoint),
+            _limiter,
+            widgets::limiter::Message::Input(*setpoint)
+        );
+        connect!(
+            _limiter@widgets::limiter::Message::Output(ref setpoint),
+            _connection,
+            widgets::connection::Message::StreamSetPoint(*setpoint)
+        );
+        connect!(
+            _control@widgets::control::Message::SetStreamRate(ref rate),
             _connection,
-            widgets::connection::Message::SendMessage(copter_com::Message::ChangeSetvalue(*setpoint))
+            widgets::connection::Message::SetStreamRate(*rate)
+        );
+        // Modes can only be selected while connected. Leave the control mode if the link drops.
+        connect!(
+            _connection@widgets::connection::Message::Connected,
+            _control,
+            widgets::control::Message::LinkUp
+        );
+        connect!(
+            _connection@widgets::connection::Message::ConnectionError,
+            _control,
+            widgets::control::Message::LinkLost
+        );
+        connect!(
+            _connection@widgets::connection::Message::Disconnect,
+            _control,
+            widgets::control::Message::LinkLost
+        );
+        // Show the target angles in the graph
+        connect!(
+            _control@widgets::control::Message::SendSetPoint(ref setpoint),
+            _graph,
+            widgets::graph::Message::SetTarget(widgets::control::angle_target(setpoint))
+        );
+
+        // Joystick input
+        connect!(
+            _joystick@widgets::joystick::Message::Input(ref roll, ref pitch, ref yaw, ref throttle),
+            _control,
+            widgets::control::Message::ExternalInput(*roll, *pitch, *yaw, *throttle)
+        );
+        connect!(
+            _joystick@widgets::joystick::Message::Arm,
+            _control,
+            widgets::control::Message::RequestArm
+        );
+        connect!(
+            _joystick@widgets::joystick::Message::Disarm,
+            _control,
+            widgets::control::Message::DisableMotor
+        );
+        connect!(
+            _joystick@widgets::joystick::Message::ModeSwitch,
+            _control,
+            widgets::control::Message::NextMode
+        );
+
+        // Keyboard input
+        connect!(
+            _keyboard@widgets::keyboard::Message::Input(ref roll, ref pitch, ref yaw, ref throttle),
+            _control,
+            widgets::control::Message::ExternalInput(*roll, *pitch, *yaw, *throttle)
+        );
+
+        // Motor test sequence. It is aborted when the control leaves the scripted mode.
+        connect!(
+            _sequence@widgets::sequence::Message::SetPoint(ref setpoint),
+            _control,
+            widgets::control::Message::ScriptedSetPoint(*setpoint)
+        );
+        connect!(
+            _control@widgets::control::Message::SelectMode(_),
+            _sequence,
+            widgets::sequence::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::StopStream,
+            _sequence,
+            widgets::sequence::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::EmergencyStop,
+            _sequence,
+            widgets::sequence::Message::Abort
+        );
+
+        // Step response experiment
+        connect!(
+            _trim@widgets::trim::Message::TrimmedAttitude(ref timestamp, ref roll, ref pitch, ref yaw),
+            _experiment,
+            widgets::experiment::Message::Attitude(*timestamp, *roll, *pitch, *yaw)
+        );
+        connect!(
+            _experiment@widgets::experiment::Message::SetPoint(ref setpoint),
+            _control,
+            widgets::control::Message::ScriptedSetPoint(*setpoint)
+        );
+        connect!(
+            _experiment@widgets::experiment::Message::Annotations(ref annotations),
+            _graph,
+            widgets::graph::Message::SetAnnotations(annotations.clone())
+        );
+        connect!(
+            _control@widgets::control::Message::SelectMode(_),
+            _experiment,
+            widgets::experiment::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::StopStream,
+            _experiment,
+            widgets::experiment::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::EmergencyStop,
+            _experiment,
+            widgets::experiment::Message::Abort
+        );
+
+        // Frequency sweep with the frequency response in the bode plot
+        connect!(
+            _trim@widgets::trim::Message::TrimmedAttitude(_, ref roll, ref pitch, ref yaw),
+            _sweep,
+            widgets::sweep::Message::Attitude(*roll, *pitch, *yaw)
+        );
+        connect!(
+            _sweep@widgets::sweep::Message::SetPoint(ref setpoint),
+            _control,
+            widgets::control::Message::ScriptedSetPoint(*setpoint)
+        );
+        connect!(
+            _sweep@widgets::sweep::Message::Response(ref response),
+            _bode,
+            widgets::bode::Message::SetData(response.clone())
+        );
+        connect!(
+            _control@widgets::control::Message::SelectMode(_),
+            _sweep,
+            widgets::sweep::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::StopStream,
+            _sweep,
+            widgets::sweep::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::EmergencyStop,
+            _sweep,
+            widgets::sweep::Message::Abort
+        );
+
+        // Relay auto-tune. The motors are disabled if the angle leaves the abort limit.
+        connect!(
+            _trim@widgets::trim::Message::TrimmedAttitude(_, ref roll, ref pitch, _),
+            _autotune,
+            widgets::autotune::Message::Attitude(*roll, *pitch)
+        );
+        connect!(
+            _autotune@widgets::autotune::Message::SetPoint(ref setpoint),
+            _control,
+            widgets::control::Message::ScriptedSetPoint(*setpoint)
+        );
+        connect!(
+            _autotune@widgets::autotune::Message::LimitExceeded,
+            _control,
+            widgets::control::Message::DisableMotor
+        );
+        connect!(
+            _control@widgets::control::Message::SelectMode(_),
+            _autotune,
+            widgets::autotune::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::StopStream,
+            _autotune,
+            widgets::autotune::Message::Abort
+        );
+        connect!(
+            _control@widgets::control::Message::EmergencyStop,
+            _autotune,
+            widgets::autotune::Message::Abort
         );
 
         window.show_all();
@@ -109,6 +417,17 @@ impl Widget for App {
             _graph,
             _connection,
             _control,
+            _joystick,
+            _keyboard,
+            _limiter,
+            _sequence,
+            _failsafe,
+            _experiment,
+            _sweep,
+            _bode,
+            _autotune,
+            _trim,
+            _signals,
         }
     }
 }
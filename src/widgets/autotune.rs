@@ -0,0 +1,424 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+/// Time between two checks of the timeout in ms
+const TICK: u32 = 100;
+
+/// Axes that can be tuned
+const AXES: [&str; 2] = ["Roll", "Pitch"];
+const ROLL: usize = 0;
+
+/// Cycles until the oscillation is considered stable
+const SETTLE_CYCLES: usize = 2;
+/// Cycles that are averaged for the result
+const MEASURE_CYCLES: usize = 4;
+
+/// Tuning rules with the factors for Kp from Ku and for Ti and Td from Pu
+const RULES: [(&str, f64, f64, f64); 2] = [
+    ("Ziegler–Nichols", 0.6, 0.5, 0.125),
+    ("Tyreus–Luyben", 1.0 / 2.2, 2.2, 1.0 / 6.3),
+];
+
+/// Units of Kp, Ki and Kd. The controller maps the angle error in ° to the PRYT output in %.
+const GAIN_UNITS: [&str; 3] = ["%/°", "%/(°·s)", "%·s/°"];
+
+/// Ultimate gain from the relay amplitude, the oscillation amplitude and the hysteresis
+fn ultimate_gain(relay: f64, amplitude: f64, hysteresis: f64) -> f64 {
+    4.0 * relay
+        / (std::f64::consts::PI
+            * (amplitude * amplitude - hysteresis * hysteresis)
+                .max(std::f64::EPSILON)
+                .sqrt())
+}
+
+/// PID gains Kp, Ki and Kd of a tuning rule
+fn rule_gains(rule: &(&str, f64, f64, f64), ku: f64, pu: f64) -> (f64, f64, f64) {
+    let &(_, kp_factor, ti_factor, td_factor) = rule;
+    let kp = kp_factor * ku;
+    let ti = ti_factor * pu;
+    let td = td_factor * pu;
+    (kp, kp / ti, kp * td)
+}
+
+/// Gains with their units
+fn format_gains((kp, ki, kd): (f64, f64, f64)) -> String {
+    format!(
+        "Kp {:.4} {}  Ki {:.4} {}  Kd {:.4} {}",
+        kp, GAIN_UNITS[0], ki, GAIN_UNITS[1], kd, GAIN_UNITS[2]
+    )
+}
+
+/// State of a running relay experiment
+struct Relay {
+    start: std::time::Instant,
+    reference: f64,
+    /// Relay output is positive
+    high: bool,
+    /// Time of the last switch to the positive output
+    last_rise: Option<f64>,
+    /// Extremes of the error since the last switch to the positive output
+    max: f64,
+    min: f64,
+    /// Period and amplitude of the finished cycles
+    cycles: Vec<(f64, f64)>,
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    relay: Option<Relay>,
+    result: Option<(f64, f64)>,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Attitude(f32, f32),
+    Start,
+    Stop,
+    Abort,
+    Tick,
+    /// Copy the gains of a rule to the clipboard
+    CopyGains(usize),
+    SetPoint(copter_com::SetValues),
+    LimitExceeded,
+}
+
+/// Suggests gains for a PID from the angle of one axis to its PRYT output.
+/// The relay drives the raw PRYT output, so the gains are for a loop that commands it in % per ° of error.
+/// Scripted setpoints skip the rate limit of the limiter, so each relay switch reaches the copter at once.
+/// copter_com has no message for PID gains, so they are only copied for the operator.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    axis: gtk::ComboBoxText,
+    relay_amplitude: gtk::Adjustment,
+    hysteresis: gtk::Adjustment,
+    reference: gtk::Adjustment,
+    throttle: gtk::Adjustment,
+    abort_angle: gtk::Adjustment,
+    timeout: gtk::Adjustment,
+    settings: gtk::Grid,
+    label_state: gtk::Label,
+    label_ultimate: gtk::Label,
+    gain_labels: Vec<gtk::Label>,
+    copy_buttons: Vec<gtk::Button>,
+    label_copied: gtk::Label,
+}
+
+impl Widget {
+    fn axis(&self) -> usize {
+        self.axis.get_active().map_or(ROLL, |axis| axis as usize)
+    }
+
+    /// PRYT setpoint with the relay output on the selected axis
+    fn setpoint(&self, output: f64) -> copter_com::SetValues {
+        let output = output as f32;
+        let throttle = self.throttle.get_value() as f32;
+        if self.axis() == ROLL {
+            copter_com::SetValues::PRYTControl((0.0, output, 0.0, throttle))
+        } else {
+            copter_com::SetValues::PRYTControl((output, 0.0, 0.0, throttle))
+        }
+    }
+
+    fn relay_output(&self, high: bool) -> f64 {
+        let amplitude = self.relay_amplitude.get_value();
+        if high {
+            amplitude
+        } else {
+            -amplitude
+        }
+    }
+
+    fn start(&mut self) {
+        if self.model.relay.is_some() {
+            return;
+        }
+        self.model.result = None;
+        self.label_ultimate.set_text("Ku: -, Pu: -");
+        for label in self.gain_labels.iter() {
+            label.set_text("-");
+        }
+        for button in self.copy_buttons.iter() {
+            button.set_sensitive(false);
+        }
+        self.model.relay = Some(Relay {
+            start: std::time::Instant::now(),
+            reference: self.reference.get_value(),
+            high: true,
+            last_rise: None,
+            max: std::f64::MIN,
+            min: std::f64::MAX,
+            cycles: Vec::new(),
+        });
+        self.settings.set_sensitive(false);
+        self.label_state.set_text("Relay oscillation running");
+        let setpoint = self.setpoint(self.relay_output(true));
+        self.model.relm.stream().emit(Message::SetPoint(setpoint));
+    }
+
+    /// End the experiment. Unless it was aborted from outside the relay output goes back to zero.
+    fn end(&mut self, state: &str, neutral: bool) {
+        if self.model.relay.take().is_none() {
+            return;
+        }
+        self.settings.set_sensitive(true);
+        self.label_state.set_text(state);
+        if neutral {
+            let setpoint = self.setpoint(0.0);
+            self.model.relm.stream().emit(Message::SetPoint(setpoint));
+        }
+    }
+
+    fn attitude(&mut self, roll: f32, pitch: f32) {
+        let angle = f64::from(if self.axis() == ROLL { roll } else { pitch });
+        if self.model.relay.is_none() {
+            return;
+        }
+        if angle.abs() > self.abort_angle.get_value() {
+            self.end(
+                &format!("Aborted: angle {:.1}° above the limit", angle),
+                false,
+            );
+            self.model.relm.stream().emit(Message::LimitExceeded);
+            return;
+        }
+
+        let hysteresis = self.hysteresis.get_value();
+        let relay = match self.model.relay.as_mut() {
+            Some(relay) => relay,
+            None => return,
+        };
+        let time = relay.start.elapsed().as_secs_f64();
+        let error = angle - relay.reference;
+        relay.max = relay.max.max(error);
+        relay.min = relay.min.min(error);
+
+        // Switch against the error once it leaves the hysteresis
+        let switch = if relay.high && error > hysteresis {
+            relay.high = false;
+            true
+        } else if !relay.high && error < -hysteresis {
+            relay.high = true;
+            if let Some(last_rise) = relay.last_rise {
+                relay
+                    .cycles
+                    .push((time - last_rise, (relay.max - relay.min) / 2.0));
+            }
+            relay.last_rise = Some(time);
+            relay.max = std::f64::MIN;
+            relay.min = std::f64::MAX;
+            true
+        } else {
+            false
+        };
+        let high = relay.high;
+        let cycles = relay.cycles.len();
+        // Average over the last cycles once the oscillation settled
+        let result = if cycles >= SETTLE_CYCLES + MEASURE_CYCLES {
+            let measured = &relay.cycles[cycles - MEASURE_CYCLES..];
+            let period = measured.iter().map(|cycle| cycle.0).sum::<f64>();
+            let amplitude = measured.iter().map(|cycle| cycle.1).sum::<f64>();
+            Some((
+                period / MEASURE_CYCLES as f64,
+                amplitude / MEASURE_CYCLES as f64,
+            ))
+        } else {
+            None
+        };
+        match result {
+            Some((period, amplitude)) => self.finish(period, amplitude, hysteresis),
+            None if switch => {
+                let setpoint = self.setpoint(self.relay_output(high));
+                self.model.relm.stream().emit(Message::SetPoint(setpoint));
+                self.label_state
+                    .set_text(&format!("Relay oscillation running, cycle {}", cycles));
+            }
+            None => (),
+        }
+    }
+
+    fn finish(&mut self, period: f64, amplitude: f64, hysteresis: f64) {
+        let ku = ultimate_gain(self.relay_amplitude.get_value(), amplitude, hysteresis);
+        self.end("Finished, copy a set of gains", true);
+        self.model.result = Some((ku, period));
+        self.label_ultimate.set_text(&format!(
+            "Ku: {:.3} {}, Pu: {:.3} s, amplitude {:.2}°",
+            ku, GAIN_UNITS[0], period, amplitude
+        ));
+        for ((rule, label), button) in RULES
+            .iter()
+            .zip(self.gain_labels.iter())
+            .zip(self.copy_buttons.iter())
+        {
+            label.set_text(&format_gains(rule_gains(rule, ku, period)));
+            button.set_sensitive(true);
+        }
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            relay: None,
+            result: None,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Attitude(roll, pitch) => self.attitude(roll, pitch),
+            Message::Start => self.start(),
+            Message::Stop => self.end("Stopped", true),
+            Message::Abort => self.end("Aborted", false),
+            Message::Tick => {
+                let timed_out = self.model.relay.as_ref().map_or(false, |relay| {
+                    relay.start.elapsed().as_secs_f64() > self.timeout.get_value()
+                });
+                if timed_out {
+                    self.end("Aborted: no stable oscillation within the timeout", true);
+                }
+            }
+            Message::CopyGains(index) => {
+                if let Some((ku, pu)) = self.model.result {
+                    let text = format!(
+                        "{} angle to PRYT output ({}): {}",
+                        AXES[self.axis()],
+                        RULES[index].0,
+                        format_gains(rule_gains(&RULES[index], ku, pu))
+                    );
+                    gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&text);
+                    self.label_copied.set_text(&format!("Copied: {}", text));
+                }
+            }
+            Message::SetPoint(_) => (),
+            Message::LimitExceeded => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("PID Auto-Tune"));
+        let root_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        root.add(&root_box);
+
+        // Settings of the relay. They are locked while it runs.
+        let settings = gtk::Grid::new();
+        settings.set_column_spacing(5);
+        root_box.add(&settings);
+
+        let label_axis = gtk::Label::new(Some("Axis"));
+        label_axis.set_xalign(0.0);
+        settings.attach(&label_axis, 0, 0, 1, 1);
+        let axis = gtk::ComboBoxText::new();
+        for &name in AXES.iter() {
+            axis.append_text(name);
+        }
+        axis.set_active(Some(ROLL as u32));
+        settings.attach(&axis, 1, 0, 1, 1);
+
+        let add_setting = |row: i32, label: &str, adjustment: &gtk::Adjustment, digits: u32| {
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            settings.attach(&label, 0, row, 1, 1);
+            settings.attach(
+                &gtk::SpinButton::new(Some(adjustment), 0.1, digits),
+                1,
+                row,
+                1,
+                1,
+            );
+        };
+        // The range of the relay amplitude is the enforced limit
+        let relay_amplitude = gtk::Adjustment::new(5.0, 0.5, 20.0, 0.5, 1.0, 0.0);
+        add_setting(1, "Relay Amplitude [%]", &relay_amplitude, 1);
+        let hysteresis = gtk::Adjustment::new(0.5, 0.0, 5.0, 0.1, 1.0, 0.0);
+        add_setting(2, "Hysteresis [°]", &hysteresis, 1);
+        let reference = gtk::Adjustment::new(0.0, -10.0, 10.0, 0.5, 1.0, 0.0);
+        add_setting(3, "Reference [°]", &reference, 1);
+        let throttle = gtk::Adjustment::new(30.0, 0.0, 100.0, 1.0, 10.0, 0.0);
+        add_setting(4, "Throttle [%]", &throttle, 0);
+        let abort_angle = gtk::Adjustment::new(20.0, 2.0, 45.0, 1.0, 5.0, 0.0);
+        add_setting(5, "Abort Angle [°]", &abort_angle, 0);
+        let timeout = gtk::Adjustment::new(30.0, 5.0, 120.0, 1.0, 10.0, 0.0);
+        add_setting(6, "Timeout [s]", &timeout, 0);
+
+        let box_run = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        root_box.add(&box_run);
+        let btn_start = gtk::Button::new();
+        btn_start.set_label("Start");
+        box_run.add(&btn_start);
+        let btn_stop = gtk::Button::new();
+        btn_stop.set_label("Stop");
+        box_run.add(&btn_stop);
+        let label_state = gtk::Label::new(Some(""));
+        box_run.add(&label_state);
+
+        // Results with the suggested gains of each rule
+        let label_ultimate = gtk::Label::new(Some("Ku: -, Pu: -"));
+        label_ultimate.set_xalign(0.0);
+        root_box.add(&label_ultimate);
+        let grid_gains = gtk::Grid::new();
+        grid_gains.set_column_spacing(5);
+        root_box.add(&grid_gains);
+        let mut gain_labels = Vec::new();
+        let mut copy_buttons = Vec::new();
+        for (row, &(name, _, _, _)) in RULES.iter().enumerate() {
+            let label_rule = gtk::Label::new(Some(name));
+            label_rule.set_xalign(0.0);
+            grid_gains.attach(&label_rule, 0, row as i32, 1, 1);
+            let label_gains = gtk::Label::new(Some("-"));
+            label_gains.set_xalign(0.0);
+            grid_gains.attach(&label_gains, 1, row as i32, 1, 1);
+            let btn_copy = gtk::Button::new();
+            btn_copy.set_label("Copy");
+            btn_copy.set_sensitive(false);
+            grid_gains.attach(&btn_copy, 2, row as i32, 1, 1);
+            connect!(relm, btn_copy, connect_clicked(_), Message::CopyGains(row));
+            gain_labels.push(label_gains);
+            copy_buttons.push(btn_copy);
+        }
+        let label_copied = gtk::Label::new(Some(""));
+        label_copied.set_xalign(0.0);
+        label_copied.set_selectable(true);
+        root_box.add(&label_copied);
+
+        connect!(relm, btn_start, connect_clicked(_), Message::Start);
+        connect!(relm, btn_stop, connect_clicked(_), Message::Stop);
+
+        Self {
+            model,
+            root,
+            axis,
+            relay_amplitude,
+            hysteresis,
+            reference,
+            throttle,
+            abort_angle,
+            timeout,
+            settings,
+            label_state,
+            label_ultimate,
+            gain_labels,
+            copy_buttons,
+            label_copied,
+        }
+    }
+}
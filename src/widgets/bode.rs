@@ -0,0 +1,186 @@
+// Things from relm
+use relm::{connect, DrawHandler, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+use gtk::DrawingArea;
+
+/// Frequency response at one frequency
+#[derive(Clone, Copy, Debug)]
+pub struct BodePoint {
+    /// Frequency in Hz
+    pub frequency: f64,
+    /// Gain in dB
+    pub gain: f64,
+    /// Phase in °
+    pub phase: f64,
+}
+
+pub struct Model {
+    draw_handler: DrawHandler<DrawingArea>,
+    data: Vec<BodePoint>,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Draw,
+    SetData(Vec<BodePoint>),
+}
+
+/// Bode plot of a measured frequency response with gain on top and phase below
+pub struct Widget {
+    model: Model,
+    drawing_area: DrawingArea,
+}
+
+impl Widget {
+    /// Draw one panel of the plot with a logarithmic x axis
+    fn draw_panel(
+        &self,
+        cx: &cairo::Context,
+        area: (f64, f64, f64, f64),
+        label: &str,
+        value: fn(&BodePoint) -> f64,
+    ) {
+        let (x, y, w, h) = area;
+
+        // Black Background
+        cx.rectangle(x, y, w, h);
+        cx.set_source_rgb(0.0, 0.0, 0.0);
+        cx.fill();
+        cx.set_source_rgb(0.0, 0.0, 0.0);
+        cx.move_to(x, y - 4.0);
+        cx.show_text(label);
+
+        if self.model.data.is_empty() {
+            return;
+        }
+
+        // Find min and max in both directions
+        let mut f_min = std::f64::MAX;
+        let mut f_max = std::f64::MIN;
+        let mut v_min = std::f64::MAX;
+        let mut v_max = std::f64::MIN;
+        for point in self.model.data.iter() {
+            f_min = f_min.min(point.frequency);
+            f_max = f_max.max(point.frequency);
+            v_min = v_min.min(value(point));
+            v_max = v_max.max(value(point));
+        }
+        let decade_min = f_min.log10().floor();
+        let decade_max = f_max.log10().ceil().max(decade_min + 1.0);
+        if (v_max - v_min).abs() <= f64::EPSILON {
+            v_max += 0.5;
+            v_min -= 0.5;
+        }
+        let to_x =
+            |frequency: f64| x + (frequency.log10() - decade_min) / (decade_max - decade_min) * w;
+        let to_y = |v: f64| y + h - (v - v_min) / (v_max - v_min) * h;
+
+        // Grid lines at 1, 2, .. 9 times each decade
+        cx.set_line_width(1.0);
+        cx.set_source_rgb(0.5, 0.5, 0.5);
+        let mut decade = decade_min;
+        while decade < decade_max {
+            for i in 1..10 {
+                let line_x = to_x(10f64.powf(decade) * i as f64);
+                cx.move_to(line_x, y);
+                cx.line_to(line_x, y + h);
+                cx.stroke();
+            }
+            cx.move_to(to_x(10f64.powf(decade)), y + h + 12.0);
+            cx.show_text(&format!("{} Hz", 10f64.powf(decade)));
+            decade += 1.0;
+        }
+        for i in 0..5 {
+            let v = v_min + (v_max - v_min) * i as f64 / 4.0;
+            cx.move_to(x, to_y(v));
+            cx.line_to(x + w, to_y(v));
+            cx.stroke();
+            cx.move_to(x - 45.0, to_y(v) + 4.0);
+            cx.show_text(&format!("{:.1}", v));
+        }
+
+        // Draw the data
+        cx.set_line_width(2.0);
+        cx.set_source_rgb(1.0, 1.0, 0.0);
+        cx.move_to(
+            to_x(self.model.data[0].frequency),
+            to_y(value(&self.model.data[0])),
+        );
+        for point in self.model.data.iter().skip(1) {
+            cx.line_to(to_x(point.frequency), to_y(value(point)));
+        }
+        cx.stroke();
+    }
+
+    fn draw(&mut self, width: i32, height: i32) {
+        let cx = self.model.draw_handler.get_context();
+
+        cx.set_source_rgb(0.8, 0.8, 0.8);
+        cx.paint();
+
+        let w = width as f64 * 0.85;
+        let h = height as f64 * 0.35;
+        let x = width as f64 * 0.1;
+        self.draw_panel(&cx, (x, height as f64 * 0.07, w, h), "Gain [dB]", |point| {
+            point.gain
+        });
+        self.draw_panel(&cx, (x, height as f64 * 0.55, w, h), "Phase [°]", |point| {
+            point.phase
+        });
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(_relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        Model {
+            draw_handler: DrawHandler::new().unwrap(),
+            data: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Draw => {
+                let allocation = self.drawing_area.get_allocation();
+                self.draw(allocation.width, allocation.height);
+            }
+            Message::SetData(data) => {
+                self.model.data = data;
+                self.drawing_area.queue_draw();
+            }
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = DrawingArea;
+
+    fn root(&self) -> Self::Root {
+        self.drawing_area.clone()
+    }
+
+    fn view(relm: &Relm<Self>, mut model: Self::Model) -> Self {
+        let drawing_area = DrawingArea::new();
+        drawing_area.set_size_request(400, 250);
+        model.draw_handler.init(&drawing_area);
+
+        connect!(
+            relm,
+            drawing_area,
+            connect_draw(_, _),
+            return (Some(Message::Draw), Inhibit(false))
+        );
+
+        Self {
+            drawing_area,
+            model,
+        }
+    }
+}
@@ -10,22 +10,53 @@ use gtk::{Button, Frame};
 // Serial Imports
 use serialport::prelude::*;
 
+/// Default baud rate used if no valid rate is selected
+const DEFAULT_BAUD_RATE: u32 = 38400;
+/// Baud rates that are tried in this order if "Auto" is selected
+const AUTO_BAUD_RATES: [u32; 6] = [38400, 115200, 57600, 19200, 9600, 230400];
+/// Time to listen for valid frames at each baud rate during the auto detection
+const AUTO_BAUD_LISTEN_TIME: std::time::Duration = std::time::Duration::from_millis(300);
+/// Read timeout of the serial port. Short enough to keep the setpoint stream on time.
+const SERIAL_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(5);
+/// Longest time the connection thread waits for a new command
+const COMMAND_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(50);
+/// Default rate of the setpoint stream in Hz
+pub const DEFAULT_STREAM_RATE: u32 = 50;
+/// Duration of one tick of the attitude timestamp in s.
+/// copter_com only transfers the raw `u32` counter and has no constant for its unit.
+/// The flight controller fills it from its millisecond system tick, so this has to follow the firmware.
+pub const TIMESTAMP_RESOLUTION: f64 = 0.001;
+/// Number of times `DisableMotor` is send on an emergency stop
+const EMERGENCY_STOP_REPEAT: usize = 5;
+/// Longest time to wait for the `DisableMotor` message to be flushed on disconnect
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
 pub struct Model {
     root: Frame,
     device_list: gtk::ComboBoxText,
+    baud_list: gtk::ComboBoxText,
     btn_connect: gtk::Button,
     btn_disconnect: gtk::Button,
     btn_refresh: gtk::Button,
+    status: gtk::Label,
     serial: Option<Box<dyn SerialPort>>,
     app_reciver: Option<relm::Channel<Message>>,
-    app_sender: Option<std::sync::mpsc::Sender<copter_com::Message>>,
+    app_sender: Option<std::sync::mpsc::Sender<Command>>,
     relm: relm::Relm<Widget>,
     ping_sequence: u16,
+    stream_rate: u32,
+    /// The sequence test was sent and no other setpoint since
+    sequence_test_sent: bool,
+    emergency_stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    /// Set on disconnect to end a running baud rate detection
+    cancel_detection: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
 
 #[derive(Msg)]
 pub enum Message {
     Connect,
+    /// The connection is open and the baud rate is known
+    Connected,
     Disconnect,
     RefreshDeviceList,
     ConnectionError,
@@ -33,6 +64,120 @@ pub enum Message {
     SendMessage(copter_com::Message),
     RecivedMsg(copter_com::Message),
     RecivedAttitude(copter_com::Attitude),
+    BaudRateDetected(u32),
+    StreamSetPoint(Option<copter_com::SetValues>),
+    SetStreamRate(u32),
+    EmergencyStop,
+    Shutdown,
+    ShutdownComplete,
+}
+
+/// Commands from the application to the connection thread
+enum Command {
+    /// Send a single message
+    Send(copter_com::Message),
+    /// Repeat the latest setpoint with the stream rate. `None` stops the stream.
+    Stream(Option<copter_com::SetValues>),
+    /// Change the stream rate in Hz
+    StreamRate(u32),
+    /// Wake the thread to handle the emergency stop flag
+    EmergencyStop,
+    /// Disarm the motors, flush the port and end the thread. The sender is notified when done.
+    Shutdown(std::sync::mpsc::Sender<()>),
+}
+
+/// Period of the setpoint stream for a rate in Hz
+fn stream_period(rate: u32) -> std::time::Duration {
+    std::time::Duration::from_secs(1) / rate.max(1)
+}
+
+/// Splits the incoming byte stream into frames and parses them
+#[derive(Default)]
+struct FrameDecoder {
+    recive_msg: bool,
+    length: Option<u8>,
+    msg: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Add a byte to the decoder. Returns the message if the byte completed a valid frame.
+    fn push(&mut self, val: u8) -> Option<copter_com::Message> {
+        // Wait for start byte
+        if (val == copter_com::START_BYTE) && !self.recive_msg {
+            self.recive_msg = true;
+            self.length = None;
+            self.msg.clear();
+        }
+
+        if !self.recive_msg {
+            return None;
+        }
+
+        // Add byte to buffer
+        self.msg.push(val);
+
+        // Check length byte
+        if self.msg.len() == 2 {
+            if val <= 30 {
+                self.length = Some(val);
+            } else {
+                self.recive_msg = false;
+            }
+        }
+
+        // Check end of message
+        if let Some(len) = self.length {
+            if (len as u16 + 2) == (self.msg.len() as u16) {
+                self.recive_msg = false;
+                self.length = None;
+                return copter_com::Message::parse(&self.msg).ok();
+            }
+        }
+
+        None
+    }
+}
+
+/// Try the baud rates from `AUTO_BAUD_RATES` one after another. At each rate a ping is send
+/// and the port is observed for frames that can be parsed. The first rate with valid traffic is returned.
+/// The detection ends without a result as soon as `cancel` is set.
+fn detect_baud_rate(
+    serial: &mut dyn SerialPort,
+    cancel: &std::sync::atomic::AtomicBool,
+) -> Option<u32> {
+    let mut buffer = [0; 128];
+    for &baud_rate in AUTO_BAUD_RATES.iter() {
+        if cancel.load(std::sync::atomic::Ordering::SeqCst) {
+            return None;
+        }
+        if serial.set_baud_rate(baud_rate).is_err() {
+            continue;
+        }
+        // Drop everything that was recived with the old baud rate
+        while serial.read(&mut buffer).is_ok() {}
+
+        let ping = copter_com::Message::Ping(copter_com::Ping { sequence: 0 });
+        if serial.write_all(ping.serialize().as_ref()).is_err() {
+            return None;
+        }
+
+        let mut decoder = FrameDecoder::default();
+        let start = std::time::Instant::now();
+        while start.elapsed() < AUTO_BAUD_LISTEN_TIME {
+            if cancel.load(std::sync::atomic::Ordering::SeqCst) {
+                return None;
+            }
+            if let Ok(byte_count) = serial.read(&mut buffer) {
+                if buffer[..byte_count]
+                    .iter()
+                    .any(|&val| decoder.push(val).is_some())
+                {
+                    return Some(baud_rate);
+                }
+            }
+        }
+    }
+    None
 }
 
 pub struct Widget {
@@ -57,19 +202,34 @@ impl Widget {
     }
 
     fn disconnect(&mut self) {
+        self.model
+            .cancel_detection
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        // Disarm the motors before the connection is closed
+        if let Some(sender) = self.model.app_sender.take() {
+            let (ack_sender, ack_reciver) = std::sync::mpsc::channel();
+            if sender.send(Command::Shutdown(ack_sender)).is_ok() {
+                ack_reciver.recv_timeout(SHUTDOWN_TIMEOUT).ok();
+            }
+        }
         self.model.serial.take();
         self.model.app_reciver.take();
-        self.model.app_sender.take();
     }
 
     fn connect(&mut self) {
+        // "auto" and every other id that is not a number starts the baud rate detection
+        let baud_rate = self
+            .model
+            .baud_list
+            .get_active_id()
+            .and_then(|id| id.parse::<u32>().ok());
         let port_settings = serialport::SerialPortSettings {
-            baud_rate: 38400,
+            baud_rate: baud_rate.unwrap_or(DEFAULT_BAUD_RATE),
             data_bits: DataBits::Eight,
             flow_control: FlowControl::None,
             parity: Parity::None,
             stop_bits: StopBits::One,
-            timeout: std::time::Duration::from_millis(50),
+            timeout: SERIAL_TIMEOUT,
         };
         // ====
         // Open a connection. If successfull spawn a thread to handle the connection.
@@ -98,67 +258,121 @@ impl Widget {
                     }
                     _ => stream.emit(msg),
                 });
-            let (app_sender, thread_reciver) = std::sync::mpsc::channel::<copter_com::Message>();
+            let (app_sender, thread_reciver) = std::sync::mpsc::channel::<Command>();
+            let stream_rate = self.model.stream_rate;
+            let emergency_stop = self.model.emergency_stop.clone();
+            emergency_stop.store(false, std::sync::atomic::Ordering::SeqCst);
+            let cancel_detection = self.model.cancel_detection.clone();
+            cancel_detection.store(false, std::sync::atomic::Ordering::SeqCst);
 
             std::thread::spawn(move || {
-                let timeout = std::time::Duration::from_millis(50);
+                // ====
+                // find the baud rate of the device if requested
+                // ====
+                if baud_rate.is_none() {
+                    match detect_baud_rate(serial.as_mut(), &cancel_detection) {
+                        Some(baud_rate) => {
+                            thread_sender
+                                .send(Message::BaudRateDetected(baud_rate))
+                                .ok();
+                        }
+                        // Disconnected during the detection
+                        None if cancel_detection.load(std::sync::atomic::Ordering::SeqCst) => {
+                            return;
+                        }
+                        None => {
+                            thread_sender.send(Message::ConnectionError).ok();
+                            return;
+                        }
+                    }
+                }
+
                 let mut buffer = [0; 128];
-                let mut recive_msg = false;
-                let mut length = None;
-                let mut msg = Vec::new();
+                let mut decoder = FrameDecoder::default();
+                let mut stream = None;
+                let mut period = stream_period(stream_rate);
+                let mut next_stream = std::time::Instant::now();
                 loop {
                     // ====
                     // check for new message to send
                     // ====
-                    match thread_reciver.recv_timeout(timeout) {
-                        Ok(msg) => {
-                            // try to send the data
-                            let buffer = msg.serialize();
-                            if serial.write_all(buffer.as_ref()).is_err() {
-                                thread_sender.send(Message::ConnectionError).ok(); // we don't handle the error because the thread ends here
-                                break; // on error drop connection
+                    let timeout = if stream.is_some() {
+                        next_stream
+                            .saturating_duration_since(std::time::Instant::now())
+                            .min(COMMAND_TIMEOUT)
+                    } else {
+                        COMMAND_TIMEOUT
+                    };
+                    let msg = match thread_reciver.recv_timeout(timeout) {
+                        Ok(Command::Send(msg)) => Some(msg),
+                        Ok(Command::Stream(setpoint)) => {
+                            // A new stream starts immediately. A running stream only takes the latest value
+                            // and keeps its timer, so frequent updates don't raise the stream rate.
+                            if stream.is_none() {
+                                next_stream = std::time::Instant::now();
                             }
+                            stream = setpoint;
+                            None
+                        }
+                        Ok(Command::StreamRate(rate)) => {
+                            period = stream_period(rate);
+                            None
+                        }
+                        Ok(Command::EmergencyStop) => None,
+                        Ok(Command::Shutdown(ack)) => {
+                            let buffer = copter_com::Message::DisableMotor.serialize();
+                            serial.write_all(buffer.as_ref()).ok();
+                            serial.flush().ok();
+                            ack.send(()).ok();
+                            break;
                         }
                         Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
                             break;
                         }
-                        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => (), // repeat the loop
+                        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => None, // repeat the loop
+                    };
+                    // ====
+                    // the emergency stop has the highest priority. Pending commands are dropped.
+                    // ====
+                    if emergency_stop.swap(false, std::sync::atomic::Ordering::SeqCst) {
+                        stream = None;
+                        while thread_reciver.try_recv().is_ok() {}
+                        let buffer = copter_com::Message::DisableMotor.serialize();
+                        for _ in 0..EMERGENCY_STOP_REPEAT {
+                            if serial.write_all(buffer.as_ref()).is_err() {
+                                thread_sender.send(Message::ConnectionError).ok();
+                                return;
+                            }
+                        }
+                        continue;
+                    }
+                    // ====
+                    // send the message and the streamed setpoint if it is due
+                    // ====
+                    let setpoint = match stream {
+                        Some(setpoint) if std::time::Instant::now() >= next_stream => {
+                            next_stream += period;
+                            // Don't try to catch up if the stream fell behind
+                            next_stream = next_stream.max(std::time::Instant::now());
+                            Some(copter_com::Message::ChangeSetvalue(setpoint))
+                        }
+                        _ => None,
+                    };
+                    for msg in msg.iter().chain(setpoint.iter()) {
+                        // try to send the data
+                        let buffer = msg.serialize();
+                        if serial.write_all(buffer.as_ref()).is_err() {
+                            thread_sender.send(Message::ConnectionError).ok(); // we don't handle the error because the thread ends here
+                            return; // on error drop connection
+                        }
                     }
                     // ====
                     // check for incoming bytes
                     // ====
                     while let Ok(byte_count) = serial.read(&mut buffer) {
                         for &val in buffer[..byte_count].iter() {
-                            // Wait for start byte
-                            if (val == copter_com::START_BYTE) && !recive_msg {
-                                recive_msg = true;
-                                length = None;
-                                msg.clear();
-                            }
-
-                            // Add byte to buffer
-                            if recive_msg {
-                                msg.push(val);
-                            }
-
-                            // Check length byte
-                            if msg.len() == 2 {
-                                if val <= 30 {
-                                    length = Some(val);
-                                } else {
-                                    recive_msg = false;
-                                }
-                            }
-
-                            // Check end of message
-                            if let Some(len) = length {
-                                if (len as u16 + 2) == (msg.len() as u16) {
-                                    if let Ok(msg) = copter_com::Message::parse(&msg) {
-                                        thread_sender.send(Message::RecivedMsg(msg)).ok();
-                                    }
-                                    recive_msg = false;
-                                    length = None;
-                                }
+                            if let Some(msg) = decoder.push(val) {
+                                thread_sender.send(Message::RecivedMsg(msg)).ok();
                             }
                         }
                     }
@@ -169,6 +383,16 @@ impl Widget {
             self.model.app_sender = Some(app_sender);
             // Set Ping Sequcne
             self.model.ping_sequence = 0;
+            self.model.sequence_test_sent = false;
+            match baud_rate {
+                Some(baud_rate) => {
+                    self.model
+                        .status
+                        .set_text(&format!("Connected at {} baud", baud_rate));
+                    self.model.relm.stream().emit(Message::Connected);
+                }
+                None => self.model.status.set_text("Detecting baud rate..."),
+            }
         } else {
             self.model.relm.stream().emit(Message::ConnectionError);
         }
@@ -179,6 +403,7 @@ impl Widget {
         self.model.btn_connect.set_sensitive(true);
         self.model.btn_disconnect.set_sensitive(false);
         self.model.device_list.set_sensitive(true);
+        self.model.baud_list.set_sensitive(true);
     }
 
     fn disable_connect(&self) {
@@ -186,6 +411,7 @@ impl Widget {
         self.model.btn_connect.set_sensitive(false);
         self.model.btn_disconnect.set_sensitive(true);
         self.model.device_list.set_sensitive(false);
+        self.model.baud_list.set_sensitive(false);
     }
 }
 
@@ -220,6 +446,10 @@ impl relm::Update for Widget {
 
         // Get the Device list combo box
         let device_list = param.get_object("ComboSerialDevice").unwrap();
+        // Get the baud rate combo box
+        let baud_list = param.get_object("ComboBaudRate").unwrap();
+        // Get the status label
+        let status = param.get_object("LabelConnectionStatus").unwrap();
         // Trigger filling of the devicelist
         relm.stream().emit(Message::RefreshDeviceList);
 
@@ -229,11 +459,17 @@ impl relm::Update for Widget {
             app_reciver: None,
             app_sender: None,
             device_list,
+            baud_list,
             relm: relm.clone(),
             btn_connect,
             btn_disconnect,
             btn_refresh,
+            status,
             ping_sequence: 0,
+            stream_rate: DEFAULT_STREAM_RATE,
+            sequence_test_sent: false,
+            emergency_stop: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            cancel_detection: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
         }
     }
 
@@ -246,27 +482,78 @@ impl relm::Update for Widget {
             Message::Disconnect => {
                 self.disconnect();
                 self.enable_connect();
+                self.model.status.set_text("Disconnected");
             }
+            Message::Shutdown => {
+                self.disconnect();
+                self.model.relm.stream().emit(Message::ShutdownComplete);
+            }
+            Message::ShutdownComplete => (),
+            Message::Connected => (),
             Message::RefreshDeviceList => self.refresh_device_list(),
             Message::ConnectionError => {
                 self.disconnect();
                 self.enable_connect();
+                self.model.status.set_text("Connection error");
             }
             Message::KeepAlive => {
                 if let Some(sender) = &mut self.model.app_sender {
                     sender
-                        .send(copter_com::Message::Ping(copter_com::Ping {
+                        .send(Command::Send(copter_com::Message::Ping(copter_com::Ping {
                             sequence: self.model.ping_sequence,
-                        }))
+                        })))
                         .ok();
                     self.model.ping_sequence += 1;
                 }
             }
             Message::SendMessage(msg) => {
                 if let Some(sender) = &mut self.model.app_sender {
-                    sender.send(msg).ok();
+                    sender.send(Command::Send(msg)).ok();
+                }
+            }
+            Message::StreamSetPoint(setpoint) => {
+                if let Some(sender) = &mut self.model.app_sender {
+                    match setpoint {
+                        // The firmware runs the sequence test on its own. Repeating it would restart the sequence.
+                        Some(copter_com::SetValues::SequenceTest) => {
+                            if !self.model.sequence_test_sent {
+                                sender.send(Command::Stream(None)).ok();
+                                sender
+                                    .send(Command::Send(copter_com::Message::ChangeSetvalue(
+                                        copter_com::SetValues::SequenceTest,
+                                    )))
+                                    .ok();
+                                self.model.sequence_test_sent = true;
+                            }
+                        }
+                        setpoint => {
+                            self.model.sequence_test_sent = false;
+                            sender.send(Command::Stream(setpoint)).ok();
+                        }
+                    }
+                }
+            }
+            Message::EmergencyStop => {
+                self.model
+                    .emergency_stop
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+                if let Some(sender) = &mut self.model.app_sender {
+                    sender.send(Command::EmergencyStop).ok();
                 }
             }
+            Message::SetStreamRate(rate) => {
+                self.model.stream_rate = rate;
+                if let Some(sender) = &mut self.model.app_sender {
+                    sender.send(Command::StreamRate(rate)).ok();
+                }
+            }
+            Message::BaudRateDetected(baud_rate) => {
+                // "Auto" stays selected, so the next connection detects the rate again
+                self.model
+                    .status
+                    .set_text(&format!("Connected at {} baud (detected)", baud_rate));
+                self.model.relm.stream().emit(Message::Connected);
+            }
             Message::RecivedMsg(_) => (),
             Message::RecivedAttitude(_) => (),
         };
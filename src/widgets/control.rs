@@ -5,18 +5,580 @@ use relm_derive::Msg;
 // GTK Imports
 use gtk::prelude::*;
 
-pub struct Model {}
+/// Maximum roll or pitch in degree that allows arming
+const MAX_ARM_TILT: f32 = 20.0;
+/// Time without telemetry after which the link is considered stale
+const LINK_STALE_TIME: std::time::Duration = std::time::Duration::from_millis(1500);
+/// The motors are disarmed if they are not spun up within this time after arming
+const ARM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// Style of the emergency stop button
+const EMERGENCY_STOP_CSS: &[u8] = b"button { background-image: none; background-color: #cc0000; color: #ffffff; font-size: 20px; font-weight: bold; }";
+
+/// Control mode that is selected in the GUI
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Idle,
+    SequenceTest,
+    DirectControl,
+    PRYTControl,
+    Stabilize,
+    AngleControl,
+    /// Setpoints of the motor test sequence or an experiment
+    Scripted,
+}
+
+impl Mode {
+    /// Name of the settings panel in the mode stack
+    fn panel(self) -> &'static str {
+        match self {
+            Mode::DirectControl => "direct",
+            Mode::PRYTControl => "pryt",
+            Mode::Stabilize => "stabilize",
+            Mode::AngleControl => "angle",
+            _ => "none",
+        }
+    }
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    mode: Mode,
+    attitude: Option<(std::time::Instant, f32, f32)>,
+    armed_at: Option<std::time::Instant>,
+    spun_up: bool,
+    latched: bool,
+    scripted_setpoint: Option<copter_com::SetValues>,
+    failsafe_tripped: bool,
+    /// A connection is open, so the setpoints reach the copter
+    connected: bool,
+    /// Open confirmation of an arm request
+    arm_dialog: Option<gtk::MessageDialog>,
+}
 
 #[derive(Msg)]
 pub enum Message {
+    RequestArm,
+    ArmConfirmed(bool),
+    CheckArming,
+    EmergencyStop,
+    ResetEmergencyStop,
+    Attitude(f32, f32),
     EnableMotor,
     DisableMotor,
     SendSetPoint(copter_com::SetValues),
+    SelectMode(Mode),
+    ScriptedSetPoint(copter_com::SetValues),
+    PRYTChanged,
+    PRYTCenter,
+    MotorChanged(usize),
+    AngleChanged,
+    AngleLimitChanged,
+    StabilizeChanged,
+    StabilizeRelease,
+    ShapingChanged,
+    ExternalInput(f64, f64, f64, f64),
+    NextMode,
+    StopStream,
+    SetStreamRate(u32),
+    /// Scripted setpoints are not rate limited, only clamped to the limits
+    SetSlewLimit(bool),
+    LinkUp,
+    LinkLost,
+    FailsafeTrip,
+    FailsafeAcknowledged,
+}
+
+/// Target roll, pitch and yaw of a setpoint for the angle control
+pub fn angle_target(setpoint: &copter_com::SetValues) -> Option<(f32, f32, f32)> {
+    match *setpoint {
+        copter_com::SetValues::AngleControl((pitch, roll, yaw, _throttle)) => {
+            Some((roll, pitch, yaw))
+        }
+        _ => None,
+    }
+}
+
+/// Modes that can be selected with the mode switch of an external input
+const SWITCH_MODES: [Mode; 3] = [Mode::PRYTControl, Mode::Stabilize, Mode::AngleControl];
+
+/// Set an adjustment from a normalized input. Centered ranges take -1..1, all others 0..1.
+fn set_normalized(adjustment: &gtk::Adjustment, value: f64) {
+    let lower = adjustment.get_lower();
+    let upper = adjustment.get_upper();
+    if lower < 0.0 {
+        adjustment.set_value(value * upper);
+    } else {
+        adjustment.set_value(lower + value * (upper - lower));
+    }
+}
+
+/// Highest throttle that is commanded by a setpoint. The sequence test spins the motors on its own.
+fn setpoint_throttle(setpoint: &copter_com::SetValues) -> f32 {
+    match *setpoint {
+        copter_com::SetValues::SequenceTest => 100.0,
+        copter_com::SetValues::DirectControl((fl, fr, rl, rr)) => fl.max(fr).max(rl).max(rr),
+        copter_com::SetValues::PRYTControl((_, _, _, throttle))
+        | copter_com::SetValues::Stabilize((_, _, _, throttle))
+        | copter_com::SetValues::AngleControl((_, _, _, throttle)) => throttle,
+    }
+}
+
+/// Name of the control mode that is commanded by the setpoint
+fn setpoint_name(setpoint: &copter_com::SetValues) -> &'static str {
+    match setpoint {
+        copter_com::SetValues::SequenceTest => "Sequence Test",
+        copter_com::SetValues::DirectControl(_) => "Direct Control",
+        copter_com::SetValues::PRYTControl(_) => "PRYT Control",
+        copter_com::SetValues::Stabilize(_) => "Stabilize",
+        copter_com::SetValues::AngleControl(_) => "Angle Control",
+    }
+}
+
+/// Channels that can be shaped with the color of their curve in the preview
+const SHAPING_CHANNELS: [(&str, (f64, f64, f64)); 4] = [
+    ("Roll", (1.0, 0.0, 0.0)),
+    ("Pitch", (0.0, 1.0, 0.0)),
+    ("Yaw", (0.0, 0.0, 1.0)),
+    ("Throttle", (1.0, 1.0, 0.0)),
+];
+const SHAPE_ROLL: usize = 0;
+const SHAPE_PITCH: usize = 1;
+const SHAPE_YAW: usize = 2;
+const SHAPE_THROTTLE: usize = 3;
+
+/// Shape a normalized input with a center deadband, expo and super rate. The full deflection stays at 1.
+fn shape_curve(x: f64, expo: f64, super_rate: f64, deadband: f64) -> f64 {
+    let magnitude = x.abs().min(1.0);
+    if magnitude <= deadband {
+        return 0.0;
+    }
+    let magnitude = (magnitude - deadband) / (1.0 - deadband);
+    let magnitude = (1.0 - expo) * magnitude + expo * magnitude.powi(3);
+    let magnitude = magnitude * (1.0 - super_rate) / (1.0 - super_rate * magnitude);
+    x.signum() * magnitude
+}
+
+/// Expo, super rate and deadband of one channel
+#[derive(Clone)]
+struct ChannelShape {
+    expo: gtk::Adjustment,
+    super_rate: gtk::Adjustment,
+    deadband: gtk::Adjustment,
+}
+
+impl ChannelShape {
+    fn apply(&self, x: f64) -> f64 {
+        shape_curve(
+            x,
+            self.expo.get_value(),
+            self.super_rate.get_value(),
+            self.deadband.get_value(),
+        )
+    }
+
+    /// Value of an input with the shaping applied to its normalized range
+    fn shaped(&self, adjustment: &gtk::Adjustment) -> f32 {
+        let lower = adjustment.get_lower();
+        let upper = adjustment.get_upper();
+        let value = adjustment.get_value();
+        let shaped = if lower < 0.0 {
+            self.apply(value / upper) * upper
+        } else {
+            lower + self.apply((value - lower) / (upper - lower)) * (upper - lower)
+        };
+        shaped as f32
+    }
+}
+
+/// Input shaping for roll, pitch, yaw and throttle with a preview of the curves.
+/// Only the stick style inputs of the PRYT and direct control are shaped.
+/// The angles and the heading of the other modes are targets and are sent as set.
+struct InputShaping {
+    channels: Vec<ChannelShape>,
+    preview: gtk::DrawingArea,
+}
+
+impl InputShaping {
+    /// Shaped pitch, roll, yaw and throttle in the order of the setpoint values
+    fn pryt(
+        &self,
+        pitch: &gtk::Adjustment,
+        roll: &gtk::Adjustment,
+        yaw: &gtk::Adjustment,
+        throttle: &gtk::Adjustment,
+    ) -> (f32, f32, f32, f32) {
+        (
+            self.channels[SHAPE_PITCH].shaped(pitch),
+            self.channels[SHAPE_ROLL].shaped(roll),
+            self.channels[SHAPE_YAW].shaped(yaw),
+            self.channels[SHAPE_THROTTLE].shaped(throttle),
+        )
+    }
+
+    /// Motor outputs of the direct control, each shaped like the throttle
+    fn motors(&self, motors: &[gtk::Adjustment]) -> (f32, f32, f32, f32) {
+        let throttle = &self.channels[SHAPE_THROTTLE];
+        (
+            throttle.shaped(&motors[0]),
+            throttle.shaped(&motors[1]),
+            throttle.shaped(&motors[2]),
+            throttle.shaped(&motors[3]),
+        )
+    }
+}
+
+/// Draw the shaping curves of all channels over the normalized input
+fn draw_shaping_preview(cx: &cairo::Context, width: f64, height: f64, channels: &[ChannelShape]) {
+    cx.set_source_rgb(0.0, 0.0, 0.0);
+    cx.paint();
+
+    // Axes through the center and the linear mapping
+    cx.set_line_width(1.0);
+    cx.set_source_rgb(0.5, 0.5, 0.5);
+    cx.move_to(width / 2.0, 0.0);
+    cx.line_to(width / 2.0, height);
+    cx.move_to(0.0, height / 2.0);
+    cx.line_to(width, height / 2.0);
+    cx.stroke();
+    cx.set_dash(&[3.0, 3.0], 0.0);
+    cx.move_to(0.0, height);
+    cx.line_to(width, 0.0);
+    cx.stroke();
+    cx.set_dash(&[], 0.0);
+
+    cx.set_line_width(1.5);
+    for (channel, &(_, color)) in channels.iter().zip(SHAPING_CHANNELS.iter()) {
+        cx.set_source_rgb(color.0, color.1, color.2);
+        for i in 0..=100 {
+            let x = i as f64 / 50.0 - 1.0;
+            let y = channel.apply(x);
+            let point_x = (x + 1.0) / 2.0 * width;
+            let point_y = (1.0 - y) / 2.0 * height;
+            if i == 0 {
+                cx.move_to(point_x, point_y);
+            } else {
+                cx.line_to(point_x, point_y);
+            }
+        }
+        cx.stroke();
+    }
+}
+
+/// Inputs for the stabilize mode. Roll, pitch and yaw return to level when released, the throttle holds its value.
+struct StabilizeInput {
+    roll: gtk::Adjustment,
+    pitch: gtk::Adjustment,
+    yaw: gtk::Adjustment,
+    throttle: gtk::Adjustment,
+}
+
+impl StabilizeInput {
+    fn setpoint(&self) -> copter_com::SetValues {
+        copter_com::SetValues::Stabilize((
+            self.pitch.get_value() as f32,
+            self.roll.get_value() as f32,
+            self.yaw.get_value() as f32,
+            self.throttle.get_value() as f32,
+        ))
+    }
+
+    /// Return to level attitude
+    fn level(&self) {
+        self.roll.set_value(0.0);
+        self.pitch.set_value(0.0);
+        self.yaw.set_value(0.0);
+    }
+}
+
+/// Inputs for the target angles and the throttle
+struct AngleInput {
+    roll: gtk::Adjustment,
+    pitch: gtk::Adjustment,
+    yaw: gtk::Adjustment,
+    throttle: gtk::Adjustment,
+    max_tilt: gtk::Adjustment,
+    max_yaw: gtk::Adjustment,
+}
+
+impl AngleInput {
+    fn setpoint(&self) -> copter_com::SetValues {
+        copter_com::SetValues::AngleControl((
+            self.pitch.get_value() as f32,
+            self.roll.get_value() as f32,
+            self.yaw.get_value() as f32,
+            self.throttle.get_value() as f32,
+        ))
+    }
+
+    /// Apply the maximum angles to the range of the inputs
+    fn apply_limits(&self) {
+        let max_tilt = self.max_tilt.get_value();
+        let max_yaw = self.max_yaw.get_value();
+        for (adjustment, max) in [
+            (&self.roll, max_tilt),
+            (&self.pitch, max_tilt),
+            (&self.yaw, max_yaw),
+        ]
+        .iter()
+        {
+            adjustment.set_lower(-max);
+            adjustment.set_upper(*max);
+            // Clamp the current value to the new range
+            adjustment.set_value(adjustment.get_value().min(*max).max(-max));
+        }
+    }
+}
+
+/// Position of the motors in the quad layout. The order matches the values of `SetValues::DirectControl`.
+const MOTOR_LAYOUT: [(&str, i32, i32); 4] = [
+    ("Front Left", 0, 0),
+    ("Front Right", 1, 0),
+    ("Rear Left", 0, 1),
+    ("Rear Right", 1, 1),
+];
+
+/// Inputs for the direct control of each motor
+struct DirectInput {
+    motors: Vec<gtk::Adjustment>,
+    link: gtk::CheckButton,
+}
+
+impl DirectInput {
+    fn setpoint(&self, shaping: &InputShaping) -> copter_com::SetValues {
+        copter_com::SetValues::DirectControl(shaping.motors(&self.motors))
+    }
+
+    /// Copy the value of one motor to all others if the motors are linked
+    fn follow(&self, index: usize) {
+        if self.link.get_active() {
+            let value = self.motors[index].get_value();
+            for motor in self.motors.iter() {
+                motor.set_value(value);
+            }
+        }
+    }
+}
+
+/// Inputs for pitch, roll, yaw and throttle
+struct PRYTInput {
+    pitch: gtk::Adjustment,
+    roll: gtk::Adjustment,
+    yaw: gtk::Adjustment,
+    throttle: gtk::Adjustment,
+}
+
+impl PRYTInput {
+    fn setpoint(&self, shaping: &InputShaping) -> copter_com::SetValues {
+        copter_com::SetValues::PRYTControl(shaping.pryt(
+            &self.pitch,
+            &self.roll,
+            &self.yaw,
+            &self.throttle,
+        ))
+    }
+
+    /// Move pitch, roll and yaw to the center and the throttle to zero
+    fn center(&self) {
+        self.pitch.set_value(0.0);
+        self.roll.set_value(0.0);
+        self.yaw.set_value(0.0);
+        self.throttle.set_value(0.0);
+    }
 }
 
 pub struct Widget {
-    _model: Model,
+    model: Model,
     root: gtk::Frame,
+    mode_stack: gtk::Stack,
+    label_active_mode: gtk::Label,
+    label_arming: gtk::Label,
+    controls: gtk::Box,
+    btn_reset: gtk::Button,
+    pryt: PRYTInput,
+    direct: DirectInput,
+    angle: AngleInput,
+    stabilize: StabilizeInput,
+    shaping: InputShaping,
+}
+
+impl Widget {
+    /// Setpoint that belongs to the active mode
+    fn setpoint(&self) -> Option<copter_com::SetValues> {
+        match self.model.mode {
+            Mode::Idle => None,
+            Mode::SequenceTest => Some(copter_com::SetValues::SequenceTest),
+            Mode::DirectControl => Some(self.direct.setpoint(&self.shaping)),
+            Mode::PRYTControl => Some(self.pryt.setpoint(&self.shaping)),
+            Mode::Stabilize => Some(self.stabilize.setpoint()),
+            Mode::AngleControl => Some(self.angle.setpoint()),
+            Mode::Scripted => self.model.scripted_setpoint,
+        }
+    }
+
+    /// Apply normalized roll, pitch, yaw and throttle from an external input to the inputs of the active mode
+    fn set_external_input(&self, roll: f64, pitch: f64, yaw: f64, throttle: f64) {
+        let inputs = match self.model.mode {
+            Mode::PRYTControl => [
+                &self.pryt.roll,
+                &self.pryt.pitch,
+                &self.pryt.yaw,
+                &self.pryt.throttle,
+            ],
+            Mode::Stabilize => [
+                &self.stabilize.roll,
+                &self.stabilize.pitch,
+                &self.stabilize.yaw,
+                &self.stabilize.throttle,
+            ],
+            Mode::AngleControl => [
+                &self.angle.roll,
+                &self.angle.pitch,
+                &self.angle.yaw,
+                &self.angle.throttle,
+            ],
+            _ => return,
+        };
+        for (adjustment, &value) in inputs.iter().zip([roll, pitch, yaw, throttle].iter()) {
+            set_normalized(adjustment, value);
+        }
+    }
+
+    /// Send the setpoint of the active mode. It is streamed until the next setpoint or a stop.
+    fn send_setpoint(&self) {
+        if let Some(setpoint) = self.setpoint() {
+            let stream = self.model.relm.stream();
+            stream.emit(Message::SetSlewLimit(self.model.mode != Mode::Scripted));
+            stream.emit(Message::SendSetPoint(setpoint));
+        } else {
+            self.model.relm.stream().emit(Message::StopStream);
+        }
+    }
+
+    /// Check if the motors may be armed. Returns the reason if not.
+    fn arm_check(&self) -> Result<(), String> {
+        if self.model.latched {
+            return Err("emergency stop is latched".to_string());
+        }
+        if self.model.failsafe_tripped {
+            return Err("tilt failsafe is not acknowledged".to_string());
+        }
+        if let Some(setpoint) = self.setpoint() {
+            if setpoint_throttle(&setpoint) > 0.0 {
+                return Err("throttle is not zero".to_string());
+            }
+        }
+        match self.model.attitude {
+            None => Err("no telemetry recived".to_string()),
+            Some((time, _, _)) if time.elapsed() > LINK_STALE_TIME => Err(format!(
+                "link is stale, last telemetry {:.1} s ago",
+                time.elapsed().as_secs_f32()
+            )),
+            Some((_, roll, pitch)) if roll.abs() > MAX_ARM_TILT || pitch.abs() > MAX_ARM_TILT => {
+                Err(format!(
+                    "tilt is too large (roll {:.1}°, pitch {:.1}°)",
+                    roll, pitch
+                ))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Ask the operator to confirm the arming. The answer is recived as `ArmConfirmed`.
+    fn confirm_arm(&mut self) {
+        if self.model.arm_dialog.is_some() {
+            return;
+        }
+        let window = self
+            .root
+            .get_toplevel()
+            .and_then(|widget| widget.downcast::<gtk::Window>().ok());
+        let dialog = gtk::MessageDialog::new(
+            window.as_ref(),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::YesNo,
+            "Arm the motors?",
+        );
+        connect!(
+            self.model.relm,
+            dialog,
+            connect_response(_, response),
+            Message::ArmConfirmed(response == gtk::ResponseType::Yes)
+        );
+        dialog.show();
+        self.model.arm_dialog = Some(dialog);
+    }
+
+    /// Close an open arm confirmation without arming
+    fn close_arm_dialog(&mut self) {
+        if let Some(dialog) = self.model.arm_dialog.take() {
+            dialog.close();
+        }
+    }
+
+    fn reject_arm(&self, reason: &str) {
+        self.label_arming
+            .set_text(&format!("Arming refused: {}", reason));
+    }
+
+    /// Leave the active mode and stop the setpoint stream
+    fn stop(&mut self) {
+        self.model.mode = Mode::Idle;
+        self.mode_stack.set_visible_child_name(Mode::Idle.panel());
+        self.model.relm.stream().emit(Message::StopStream);
+    }
+}
+
+/// Add a row with a label, a slider and a spin button to the grid. Slider and spin button share the returned adjustment.
+fn add_axis_input(
+    grid: &gtk::Grid,
+    row: i32,
+    label: &str,
+    lower: f64,
+    upper: f64,
+) -> gtk::Adjustment {
+    add_axis_slider(grid, row, label, lower, upper).get_adjustment()
+}
+
+/// Same as `add_axis_input` but returns the slider
+fn add_axis_slider(grid: &gtk::Grid, row: i32, label: &str, lower: f64, upper: f64) -> gtk::Scale {
+    let adjustment = gtk::Adjustment::new(0.0, lower, upper, 1.0, 10.0, 0.0);
+
+    let label = gtk::Label::new(Some(label));
+    label.set_xalign(0.0);
+    grid.attach(&label, 0, row, 1, 1);
+
+    let scale = gtk::Scale::new(gtk::Orientation::Horizontal, Some(&adjustment));
+    scale.set_draw_value(false);
+    scale.set_hexpand(true);
+    scale.set_size_request(150, -1);
+    grid.attach(&scale, 1, row, 1, 1);
+
+    let spin = gtk::SpinButton::new(Some(&adjustment), 1.0, 1);
+    grid.attach(&spin, 2, row, 1, 1);
+
+    scale
+}
+
+/// Add a vertical slider with a label and a spin button for a single motor to the grid
+fn add_motor_input(grid: &gtk::Grid, left: i32, top: i32, label: &str) -> gtk::Adjustment {
+    let adjustment = gtk::Adjustment::new(0.0, 0.0, 100.0, 1.0, 10.0, 0.0);
+
+    let motor_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    grid.attach(&motor_box, left, top, 1, 1);
+
+    motor_box.add(&gtk::Label::new(Some(label)));
+
+    let scale = gtk::Scale::new(gtk::Orientation::Vertical, Some(&adjustment));
+    scale.set_inverted(true);
+    scale.set_draw_value(false);
+    scale.set_size_request(-1, 100);
+    motor_box.pack_start(&scale, true, true, 0);
+
+    let spin = gtk::SpinButton::new(Some(&adjustment), 1.0, 1);
+    motor_box.add(&spin);
+
+    adjustment
 }
 
 impl relm::Update for Widget {
@@ -24,11 +586,190 @@ impl relm::Update for Widget {
     type ModelParam = ();
     type Msg = Message;
 
-    fn model(_relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
-        Model {}
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), 200, || Message::CheckArming);
+
+        Model {
+            relm: relm.clone(),
+            mode: Mode::Idle,
+            attitude: None,
+            armed_at: None,
+            spun_up: false,
+            latched: false,
+            scripted_setpoint: None,
+            failsafe_tripped: false,
+            connected: false,
+            arm_dialog: None,
+        }
     }
 
-    fn update(&mut self, _event: Self::Msg) {}
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::SelectMode(mode) => {
+                // No mode can be selected without a connection or until the emergency stop is reset
+                if !self.model.connected {
+                    self.label_active_mode
+                        .set_text("Active Mode: - (not connected)");
+                } else if !self.model.latched {
+                    self.model.mode = mode;
+                    self.mode_stack.set_visible_child_name(mode.panel());
+                    self.send_setpoint();
+                }
+            }
+            Message::ScriptedSetPoint(setpoint) => {
+                // A sequence or an experiment takes over without selecting a mode.
+                // Without a connection the stop aborts it.
+                if !self.model.connected {
+                    self.stop();
+                    self.label_active_mode
+                        .set_text("Active Mode: - (not connected)");
+                } else if !self.model.latched {
+                    self.model.mode = Mode::Scripted;
+                    self.model.scripted_setpoint = Some(setpoint);
+                    self.mode_stack
+                        .set_visible_child_name(Mode::Scripted.panel());
+                    self.send_setpoint();
+                }
+            }
+            Message::PRYTChanged => {
+                if self.model.mode == Mode::PRYTControl {
+                    self.send_setpoint();
+                }
+            }
+            Message::PRYTCenter => self.pryt.center(),
+            Message::MotorChanged(index) => {
+                self.direct.follow(index);
+                if self.model.mode == Mode::DirectControl {
+                    self.send_setpoint();
+                }
+            }
+            Message::AngleChanged => {
+                if self.model.mode == Mode::AngleControl {
+                    self.send_setpoint();
+                }
+            }
+            Message::AngleLimitChanged => self.angle.apply_limits(),
+            Message::StabilizeChanged => {
+                if self.model.mode == Mode::Stabilize {
+                    self.send_setpoint();
+                }
+            }
+            Message::StabilizeRelease => self.stabilize.level(),
+            Message::ShapingChanged => {
+                self.shaping.preview.queue_draw();
+                if [Mode::PRYTControl, Mode::DirectControl].contains(&self.model.mode) {
+                    self.send_setpoint();
+                }
+            }
+            Message::ExternalInput(roll, pitch, yaw, throttle) => {
+                self.set_external_input(roll, pitch, yaw, throttle)
+            }
+            Message::NextMode => {
+                let next = SWITCH_MODES
+                    .iter()
+                    .position(|&mode| mode == self.model.mode)
+                    .map_or(0, |index| (index + 1) % SWITCH_MODES.len());
+                self.model
+                    .relm
+                    .stream()
+                    .emit(Message::SelectMode(SWITCH_MODES[next]));
+            }
+            Message::RequestArm => match self.arm_check() {
+                Err(reason) => self.reject_arm(&reason),
+                Ok(()) => self.confirm_arm(),
+            },
+            // The state may have changed while the dialog was open, so it is checked again
+            Message::ArmConfirmed(confirmed) => match self.model.arm_dialog.take() {
+                None => (),
+                Some(dialog) if !confirmed => {
+                    dialog.close();
+                    self.reject_arm("not confirmed");
+                }
+                Some(dialog) => {
+                    dialog.close();
+                    match self.arm_check() {
+                        Err(reason) => self.reject_arm(&reason),
+                        Ok(()) => {
+                            self.model.armed_at = Some(std::time::Instant::now());
+                            self.model.spun_up = false;
+                            self.label_arming.set_text("Armed");
+                            self.model.relm.stream().emit(Message::EnableMotor);
+                        }
+                    }
+                }
+            },
+            Message::CheckArming => {
+                if let Some(armed_at) = self.model.armed_at {
+                    if !self.model.spun_up && armed_at.elapsed() > ARM_TIMEOUT {
+                        self.model.armed_at = None;
+                        self.label_arming.set_text(&format!(
+                            "Disarmed: motors not spun up within {} s",
+                            ARM_TIMEOUT.as_secs()
+                        ));
+                        self.model.relm.stream().emit(Message::DisableMotor);
+                    }
+                }
+            }
+            Message::EmergencyStop => {
+                self.close_arm_dialog();
+                self.model.latched = true;
+                self.model.armed_at = None;
+                self.label_arming
+                    .set_text("EMERGENCY STOP\nReset to continue");
+                self.controls.set_sensitive(false);
+                self.btn_reset.set_sensitive(true);
+                self.stop();
+            }
+            Message::ResetEmergencyStop => {
+                self.model.latched = false;
+                self.label_arming.set_text("Disarmed");
+                self.controls.set_sensitive(true);
+                self.btn_reset.set_sensitive(false);
+            }
+            Message::Attitude(roll, pitch) => {
+                self.model.attitude = Some((std::time::Instant::now(), roll, pitch));
+            }
+            Message::EnableMotor => (),
+            Message::DisableMotor => {
+                if self.model.armed_at.take().is_some() {
+                    self.label_arming.set_text("Disarmed");
+                }
+                self.stop();
+            }
+            Message::LinkUp => self.model.connected = true,
+            Message::LinkLost => {
+                self.model.connected = false;
+                // The copter can't be armed without the link, so the arm timeout ends too
+                self.close_arm_dialog();
+                if self.model.armed_at.take().is_some() {
+                    self.label_arming.set_text("Disarmed (link lost)");
+                }
+                self.stop();
+            }
+            Message::FailsafeTrip => {
+                self.model.failsafe_tripped = true;
+                self.model.armed_at = None;
+                self.label_arming
+                    .set_text("Disarmed: tilt failsafe\nAcknowledge to arm");
+                self.model.relm.stream().emit(Message::DisableMotor);
+            }
+            Message::FailsafeAcknowledged => {
+                self.model.failsafe_tripped = false;
+                self.label_arming.set_text("Disarmed");
+            }
+            Message::StopStream => self.label_active_mode.set_text("Active Mode: -"),
+            Message::SetStreamRate(_) => (),
+            Message::SetSlewLimit(_) => (),
+            Message::SendSetPoint(setpoint) => {
+                if self.model.armed_at.is_some() && setpoint_throttle(&setpoint) > 0.0 {
+                    self.model.spun_up = true;
+                }
+                // Show the mode that was commanded last
+                self.label_active_mode
+                    .set_text(&format!("Active Mode: {}", setpoint_name(&setpoint)));
+            }
+        }
+    }
 }
 
 impl relm::Widget for Widget {
@@ -38,10 +779,50 @@ impl relm::Widget for Widget {
         self.root.clone()
     }
 
-    fn view(relm: &Relm<Self>, _model: Self::Model) -> Self {
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
         let root = gtk::Frame::new(Some("Control"));
+        let frame_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        root.add(&frame_box);
+
+        // Emergency stop
+        let box_emergency = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        frame_box.add(&box_emergency);
+        let btn_emergency_stop = gtk::Button::new();
+        btn_emergency_stop.set_label("E-STOP");
+        btn_emergency_stop.set_size_request(-1, 60);
+        let css = gtk::CssProvider::new();
+        if css.load_from_data(EMERGENCY_STOP_CSS).is_ok() {
+            btn_emergency_stop
+                .get_style_context()
+                .add_provider(&css, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+        }
+        box_emergency.pack_start(&btn_emergency_stop, true, true, 0);
+        let btn_reset = gtk::Button::new();
+        btn_reset.set_label("Reset");
+        btn_reset.set_sensitive(false);
+        box_emergency.add(&btn_reset);
+
+        // Indicator of the active mode
+        let label_active_mode = gtk::Label::new(Some("Active Mode: -"));
+        label_active_mode.set_xalign(0.0);
+        frame_box.add(&label_active_mode);
+
+        // Rate of the setpoint stream
+        let box_rate = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        frame_box.add(&box_rate);
+        box_rate.add(&gtk::Label::new(Some("Stream Rate [Hz]")));
+        let stream_rate = gtk::Adjustment::new(
+            crate::widgets::connection::DEFAULT_STREAM_RATE as f64,
+            20.0,
+            100.0,
+            1.0,
+            10.0,
+            0.0,
+        );
+        box_rate.add(&gtk::SpinButton::new(Some(&stream_rate), 1.0, 0));
+
         let root_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
-        root.add(&root_box);
+        frame_box.add(&root_box);
 
         // Buttons for enable disable motors
         let box_motors = gtk::ButtonBox::new(gtk::Orientation::Vertical);
@@ -52,6 +833,10 @@ impl relm::Widget for Widget {
         let btn_disable_motor = gtk::Button::new();
         btn_disable_motor.set_label("Disable Motor");
         box_motors.add(&btn_disable_motor);
+        let label_arming = gtk::Label::new(Some("Disarmed"));
+        label_arming.set_line_wrap(true);
+        label_arming.set_max_width_chars(20);
+        box_motors.add(&label_arming);
 
         // Buttons for control Mode
         let box_mode = gtk::ButtonBox::new(gtk::Orientation::Vertical);
@@ -77,12 +862,170 @@ impl relm::Widget for Widget {
         btn_angle_ctrl.set_label("Angle Control");
         box_mode.add(&btn_angle_ctrl);
 
+        // Settings of the selected mode
+        let mode_stack = gtk::Stack::new();
+        root_box.add(&mode_stack);
+        mode_stack.add_named(&gtk::Label::new(None), Mode::Idle.panel());
+
+        // PRYT Control
+        let grid_pryt = gtk::Grid::new();
+        grid_pryt.set_column_spacing(5);
+        mode_stack.add_named(&grid_pryt, Mode::PRYTControl.panel());
+        let pryt = PRYTInput {
+            pitch: add_axis_input(&grid_pryt, 0, "Pitch [%]", -100.0, 100.0),
+            roll: add_axis_input(&grid_pryt, 1, "Roll [%]", -100.0, 100.0),
+            yaw: add_axis_input(&grid_pryt, 2, "Yaw [%]", -100.0, 100.0),
+            throttle: add_axis_input(&grid_pryt, 3, "Throttle [%]", 0.0, 100.0),
+        };
+        let btn_pryt_center = gtk::Button::new();
+        btn_pryt_center.set_label("Center");
+        grid_pryt.attach(&btn_pryt_center, 0, 4, 3, 1);
+
+        // Direct Control
+        let grid_direct = gtk::Grid::new();
+        grid_direct.set_column_spacing(5);
+        grid_direct.set_row_spacing(5);
+        mode_stack.add_named(&grid_direct, Mode::DirectControl.panel());
+        let direct = DirectInput {
+            motors: MOTOR_LAYOUT
+                .iter()
+                .map(|&(label, left, top)| add_motor_input(&grid_direct, left, top, label))
+                .collect(),
+            link: gtk::CheckButton::with_label("Link all"),
+        };
+        grid_direct.attach(&direct.link, 0, 2, 2, 1);
+
+        // Stabilize
+        let grid_stabilize = gtk::Grid::new();
+        grid_stabilize.set_column_spacing(5);
+        mode_stack.add_named(&grid_stabilize, Mode::Stabilize.panel());
+        let stabilize_sliders = [
+            add_axis_slider(&grid_stabilize, 0, "Roll [°]", -30.0, 30.0),
+            add_axis_slider(&grid_stabilize, 1, "Pitch [°]", -30.0, 30.0),
+            add_axis_slider(&grid_stabilize, 2, "Yaw [°/s]", -90.0, 90.0),
+        ];
+        let stabilize = StabilizeInput {
+            roll: stabilize_sliders[0].get_adjustment(),
+            pitch: stabilize_sliders[1].get_adjustment(),
+            yaw: stabilize_sliders[2].get_adjustment(),
+            throttle: add_axis_input(&grid_stabilize, 3, "Throttle [%]", 0.0, 100.0),
+        };
+
+        // Angle Control
+        let grid_angle = gtk::Grid::new();
+        grid_angle.set_column_spacing(5);
+        mode_stack.add_named(&grid_angle, Mode::AngleControl.panel());
+        let angle = AngleInput {
+            roll: add_axis_input(&grid_angle, 0, "Roll [°]", -30.0, 30.0),
+            pitch: add_axis_input(&grid_angle, 1, "Pitch [°]", -30.0, 30.0),
+            yaw: add_axis_input(&grid_angle, 2, "Yaw [°]", -180.0, 180.0),
+            throttle: add_axis_input(&grid_angle, 3, "Throttle [%]", 0.0, 100.0),
+            max_tilt: gtk::Adjustment::new(30.0, 1.0, 90.0, 1.0, 10.0, 0.0),
+            max_yaw: gtk::Adjustment::new(180.0, 1.0, 180.0, 1.0, 10.0, 0.0),
+        };
+        let label_max_tilt = gtk::Label::new(Some("Max Roll/Pitch [°]"));
+        label_max_tilt.set_xalign(0.0);
+        grid_angle.attach(&label_max_tilt, 0, 4, 1, 1);
+        grid_angle.attach(
+            &gtk::SpinButton::new(Some(&angle.max_tilt), 1.0, 0),
+            2,
+            4,
+            1,
+            1,
+        );
+        let label_max_yaw = gtk::Label::new(Some("Max Yaw [°]"));
+        label_max_yaw.set_xalign(0.0);
+        grid_angle.attach(&label_max_yaw, 0, 5, 1, 1);
+        grid_angle.attach(
+            &gtk::SpinButton::new(Some(&angle.max_yaw), 1.0, 0),
+            2,
+            5,
+            1,
+            1,
+        );
+
+        // Input shaping with a preview of the curves
+        let expander_shaping = gtk::Expander::new(Some("Input Shaping (PRYT and Direct)"));
+        frame_box.add(&expander_shaping);
+        let box_shaping = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        expander_shaping.add(&box_shaping);
+        let grid_shaping = gtk::Grid::new();
+        grid_shaping.set_column_spacing(5);
+        box_shaping.add(&grid_shaping);
+        for (column, &label) in ["Expo", "Super Rate", "Deadband"].iter().enumerate() {
+            grid_shaping.attach(&gtk::Label::new(Some(label)), column as i32 + 1, 0, 1, 1);
+        }
+        let mut shaping_channels = Vec::new();
+        for (row, &(label, _)) in SHAPING_CHANNELS.iter().enumerate() {
+            let row = row as i32 + 1;
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            grid_shaping.attach(&label, 0, row, 1, 1);
+            let channel = ChannelShape {
+                expo: gtk::Adjustment::new(0.0, 0.0, 1.0, 0.05, 0.1, 0.0),
+                super_rate: gtk::Adjustment::new(0.0, 0.0, 0.95, 0.05, 0.1, 0.0),
+                deadband: gtk::Adjustment::new(0.0, 0.0, 0.5, 0.01, 0.1, 0.0),
+            };
+            for (column, adjustment) in [&channel.expo, &channel.super_rate, &channel.deadband]
+                .iter()
+                .enumerate()
+            {
+                grid_shaping.attach(
+                    &gtk::SpinButton::new(Some(*adjustment), 0.05, 2),
+                    column as i32 + 1,
+                    row,
+                    1,
+                    1,
+                );
+                connect!(
+                    relm,
+                    adjustment,
+                    connect_value_changed(_),
+                    Message::ShapingChanged
+                );
+            }
+            shaping_channels.push(channel);
+        }
+        let preview = gtk::DrawingArea::new();
+        preview.set_size_request(120, 120);
+        box_shaping.add(&preview);
+        let shaping = InputShaping {
+            channels: shaping_channels,
+            preview,
+        };
+        {
+            // The preview reads the values directly from the adjustments
+            let channels = shaping.channels.clone();
+            shaping.preview.connect_draw(move |area, cx| {
+                let allocation = area.get_allocation();
+                draw_shaping_preview(
+                    cx,
+                    allocation.width as f64,
+                    allocation.height as f64,
+                    &channels,
+                );
+                Inhibit(false)
+            });
+        }
+
         // Connect Button events
+        connect!(
+            relm,
+            btn_emergency_stop,
+            connect_clicked(_),
+            Message::EmergencyStop
+        );
+        connect!(
+            relm,
+            btn_reset,
+            connect_clicked(_),
+            Message::ResetEmergencyStop
+        );
         connect!(
             relm,
             btn_enable_motor,
             connect_clicked(_),
-            Message::EnableMotor
+            Message::RequestArm
         );
         connect!(
             relm,
@@ -94,17 +1037,122 @@ impl relm::Widget for Widget {
             relm,
             btn_sequence,
             connect_clicked(_),
-            Message::SendSetPoint(copter_com::SetValues::SequenceTest)
+            Message::SelectMode(Mode::SequenceTest)
         );
         connect!(
             relm,
             btn_direct_ctrl,
             connect_clicked(_),
-            Message::SendSetPoint(copter_com::SetValues::DirectControl((
-                10.0, 10.0, 10.0, 10.0
-            )))
+            Message::SelectMode(Mode::DirectControl)
+        );
+        connect!(
+            relm,
+            btn_pryt_ctrl,
+            connect_clicked(_),
+            Message::SelectMode(Mode::PRYTControl)
+        );
+        connect!(
+            relm,
+            btn_stabalize,
+            connect_clicked(_),
+            Message::SelectMode(Mode::Stabilize)
+        );
+        connect!(
+            relm,
+            btn_angle_ctrl,
+            connect_clicked(_),
+            Message::SelectMode(Mode::AngleControl)
         );
 
-        Self { root, _model }
+        connect!(
+            relm,
+            stream_rate,
+            connect_value_changed(adjustment),
+            Message::SetStreamRate(adjustment.get_value() as u32)
+        );
+
+        // Connect PRYT inputs
+        for adjustment in [&pryt.pitch, &pryt.roll, &pryt.yaw, &pryt.throttle].iter() {
+            connect!(
+                relm,
+                adjustment,
+                connect_value_changed(_),
+                Message::PRYTChanged
+            );
+        }
+        connect!(
+            relm,
+            btn_pryt_center,
+            connect_clicked(_),
+            Message::PRYTCenter
+        );
+
+        // Connect direct control inputs
+        for (index, adjustment) in direct.motors.iter().enumerate() {
+            connect!(
+                relm,
+                adjustment,
+                connect_value_changed(_),
+                Message::MotorChanged(index)
+            );
+        }
+
+        // Connect stabilize inputs
+        for adjustment in [
+            &stabilize.roll,
+            &stabilize.pitch,
+            &stabilize.yaw,
+            &stabilize.throttle,
+        ]
+        .iter()
+        {
+            connect!(
+                relm,
+                adjustment,
+                connect_value_changed(_),
+                Message::StabilizeChanged
+            );
+        }
+        for slider in stabilize_sliders.iter() {
+            connect!(
+                relm,
+                slider,
+                connect_button_release_event(_, _),
+                return (Some(Message::StabilizeRelease), Inhibit(false))
+            );
+        }
+
+        // Connect angle control inputs
+        for adjustment in [&angle.roll, &angle.pitch, &angle.yaw, &angle.throttle].iter() {
+            connect!(
+                relm,
+                adjustment,
+                connect_value_changed(_),
+                Message::AngleChanged
+            );
+        }
+        for adjustment in [&angle.max_tilt, &angle.max_yaw].iter() {
+            connect!(
+                relm,
+                adjustment,
+                connect_value_changed(_),
+                Message::AngleLimitChanged
+            );
+        }
+
+        Self {
+            model,
+            root,
+            mode_stack,
+            label_active_mode,
+            label_arming,
+            controls: root_box,
+            btn_reset,
+            pryt,
+            direct,
+            angle,
+            stabilize,
+            shaping,
+        }
     }
 }
@@ -0,0 +1,470 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+use crate::widgets::connection::TIMESTAMP_RESOLUTION;
+use crate::widgets::graph::Annotation;
+
+/// Time between two checks of the experiment phase in ms
+const TICK: u32 = 20;
+
+/// Axes that can be stepped. The index is the same as the series in the graph.
+const AXES: [&str; 2] = ["Roll", "Pitch"];
+const ROLL: usize = 0;
+
+/// Columns of the tuning history
+const HISTORY_COLUMNS: [&str; 7] = [
+    "#",
+    "Axis",
+    "Step [°]",
+    "Rise [s]",
+    "Overshoot [%]",
+    "Settling [s]",
+    "Error [°]",
+];
+
+/// Metrics of a step response. Times are in s after the step.
+#[derive(Debug, Clone, Copy)]
+struct StepMetrics {
+    /// Times when 10 % and 90 % of the step are reached
+    rise: Option<(f64, f64)>,
+    /// Highest value beyond the target in % of the step with the time
+    overshoot: (f64, f64),
+    /// Time after which the response stays within the tolerance band
+    settling: Option<f64>,
+    /// Target minus the mean of the last fifth of the response
+    steady_state_error: f64,
+}
+
+/// Calculate the metrics of a step response from `from` to `to`.
+/// The samples are the time after the step in s and the measured value.
+fn step_metrics(samples: &[(f64, f64)], from: f64, to: f64, tolerance: f64) -> StepMetrics {
+    let step = to - from;
+    // Progress of the response from 0 at the start to 1 at the target
+    let progress = |value: f64| {
+        if step.abs() <= std::f64::EPSILON {
+            1.0
+        } else {
+            (value - from) / step
+        }
+    };
+
+    let rise_start = samples
+        .iter()
+        .find(|&&(_, value)| progress(value) >= 0.1)
+        .map(|&(time, _)| time);
+    let rise_end = samples
+        .iter()
+        .find(|&&(_, value)| progress(value) >= 0.9)
+        .map(|&(time, _)| time);
+    let rise = match (rise_start, rise_end) {
+        (Some(start), Some(end)) => Some((start, end)),
+        _ => None,
+    };
+
+    let overshoot = samples
+        .iter()
+        .map(|&(time, value)| (((progress(value) - 1.0) * 100.0).max(0.0), time))
+        .fold(
+            (0.0, 0.0),
+            |max, sample| if sample.0 > max.0 { sample } else { max },
+        );
+
+    // Settled after the last sample outside of the band
+    let band = tolerance * step.abs();
+    let settling = match samples
+        .iter()
+        .rposition(|&(_, value)| (value - to).abs() > band)
+    {
+        Some(index) if index + 1 < samples.len() => Some(samples[index + 1].0),
+        Some(_) => None,
+        None => samples.first().map(|&(time, _)| time),
+    };
+
+    let tail_len = (samples.len() / 5).max(1).min(samples.len());
+    let tail = &samples[samples.len() - tail_len..];
+    let steady_state_error = if tail.is_empty() {
+        0.0
+    } else {
+        to - tail.iter().map(|&(_, value)| value).sum::<f64>() / tail.len() as f64
+    };
+
+    StepMetrics {
+        rise,
+        overshoot,
+        settling,
+        steady_state_error,
+    }
+}
+
+/// State of a running experiment
+enum Phase {
+    Idle,
+    /// Holding the start angle until the copter settled
+    Hold(std::time::Instant),
+    /// Recording the response after the step. The timestamp of the first sample is the time of the step.
+    Record(std::time::Instant, Option<u32>),
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    phase: Phase,
+    /// Latest attitude as timestamp, roll, pitch and yaw
+    attitude: Option<(u32, f32, f32, f32)>,
+    /// Yaw at the start of the experiment. It is kept during the step.
+    yaw: f32,
+    samples: Vec<(f64, f64)>,
+    runs: u32,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Attitude(u32, f32, f32, f32),
+    Run,
+    /// Abort by the operator. The copter is levelled with zero throttle.
+    Stop,
+    /// Abort because the control left the scripted mode. The step is no longer streamed.
+    Abort,
+    Tick,
+    SetPoint(copter_com::SetValues),
+    Annotations(Vec<Annotation>),
+}
+
+/// Applies an angle step and measures the response of the angle control.
+/// Scripted setpoints skip the rate limit of the limiter, so the step reaches the copter at once
+/// and the metrics describe the copter. The limits still clamp the angles.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    axis: gtk::ComboBoxText,
+    from: gtk::Adjustment,
+    to: gtk::Adjustment,
+    throttle: gtk::Adjustment,
+    hold: gtk::Adjustment,
+    record: gtk::Adjustment,
+    tolerance: gtk::Adjustment,
+    settings: gtk::Grid,
+    label_state: gtk::Label,
+    history: gtk::ListStore,
+}
+
+impl Widget {
+    fn axis(&self) -> usize {
+        self.axis.get_active().map_or(ROLL, |axis| axis as usize)
+    }
+
+    /// Angle setpoint with the selected axis at `angle`
+    fn setpoint(&self, angle: f64) -> copter_com::SetValues {
+        let angle = angle as f32;
+        let throttle = self.throttle.get_value() as f32;
+        if self.axis() == ROLL {
+            copter_com::SetValues::AngleControl((0.0, angle, self.model.yaw, throttle))
+        } else {
+            copter_com::SetValues::AngleControl((angle, 0.0, self.model.yaw, throttle))
+        }
+    }
+
+    /// Level setpoint with zero throttle that ends an experiment
+    fn neutral(&self) {
+        self.model
+            .relm
+            .stream()
+            .emit(Message::SetPoint(copter_com::SetValues::AngleControl((
+                0.0,
+                0.0,
+                self.model.yaw,
+                0.0,
+            ))));
+    }
+
+    fn set_phase(&mut self, phase: Phase, state: &str) {
+        self.settings.set_sensitive(matches!(phase, Phase::Idle));
+        self.model.phase = phase;
+        self.label_state.set_text(state);
+    }
+
+    fn run(&mut self) {
+        if !matches!(self.model.phase, Phase::Idle) {
+            return;
+        }
+        let yaw = match self.model.attitude {
+            Some((_, _, _, yaw)) => yaw,
+            None => {
+                self.label_state.set_text("No telemetry recived");
+                return;
+            }
+        };
+        self.model.yaw = yaw;
+        self.model.samples.clear();
+        let setpoint = self.setpoint(self.from.get_value());
+        self.model.relm.stream().emit(Message::SetPoint(setpoint));
+        let until =
+            std::time::Instant::now() + std::time::Duration::from_secs_f64(self.hold.get_value());
+        self.set_phase(Phase::Hold(until), "Holding start angle");
+    }
+
+    fn record(&mut self, timestamp: u32, roll: f32, pitch: f32) {
+        if let Phase::Record(_, ref mut start) = self.model.phase {
+            let start = *start.get_or_insert(timestamp);
+            let value = if self.axis() == ROLL { roll } else { pitch };
+            let time = timestamp.wrapping_sub(start) as f64 * TIMESTAMP_RESOLUTION;
+            self.model.samples.push((time, value as f64));
+        }
+    }
+
+    /// Level the copter, evaluate the recorded response, add it to the history and show it in the graph
+    fn finish(&mut self, step_timestamp: Option<u32>) {
+        self.neutral();
+        self.set_phase(Phase::Idle, "Finished");
+        let step_timestamp = match step_timestamp {
+            Some(timestamp) => timestamp,
+            None => {
+                self.label_state.set_text("No telemetry recorded");
+                return;
+            }
+        };
+        let from = self.from.get_value();
+        let to = self.to.get_value();
+        let tolerance = self.tolerance.get_value() / 100.0;
+        let metrics = step_metrics(&self.model.samples, from, to, tolerance);
+
+        self.model.runs += 1;
+        let format_time =
+            |time: Option<f64>| time.map_or("-".to_string(), |time| format!("{:.3}", time));
+        self.history.insert_with_values(
+            None,
+            &[0, 1, 2, 3, 4, 5, 6],
+            &[
+                &self.model.runs.to_string(),
+                &AXES[self.axis()],
+                &format!("{:.1} → {:.1}", from, to),
+                &format_time(metrics.rise.map(|(start, end)| end - start)),
+                &format!("{:.1}", metrics.overshoot.0),
+                &format_time(metrics.settling),
+                &format!("{:.2}", metrics.steady_state_error),
+            ],
+        );
+
+        // Annotations are in the units of the attitude timestamp
+        let to_graph = |time: f64| step_timestamp as f64 + time / TIMESTAMP_RESOLUTION;
+        let band = tolerance * (to - from).abs();
+        let mut annotations = vec![
+            Annotation::Band(to - band, to + band),
+            Annotation::Vertical(to_graph(0.0), "Step".to_string()),
+        ];
+        if let Some((start, end)) = metrics.rise {
+            annotations.push(Annotation::Vertical(to_graph(start), "10 %".to_string()));
+            annotations.push(Annotation::Vertical(to_graph(end), "90 %".to_string()));
+        }
+        if let Some(settling) = metrics.settling {
+            annotations.push(Annotation::Vertical(
+                to_graph(settling),
+                format!("Settled {:.2} s", settling),
+            ));
+        }
+        if metrics.overshoot.0 > 0.0 {
+            let (overshoot, time) = metrics.overshoot;
+            let peak = to + overshoot / 100.0 * (to - from);
+            annotations.push(Annotation::Point(
+                to_graph(time),
+                peak,
+                format!("{:.1} %", overshoot),
+            ));
+        }
+        self.model
+            .relm
+            .stream()
+            .emit(Message::Annotations(annotations));
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            phase: Phase::Idle,
+            attitude: None,
+            yaw: 0.0,
+            samples: Vec::new(),
+            runs: 0,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Attitude(timestamp, roll, pitch, yaw) => {
+                self.model.attitude = Some((timestamp, roll, pitch, yaw));
+                self.record(timestamp, roll, pitch);
+            }
+            Message::Run => self.run(),
+            Message::Stop => {
+                if !matches!(self.model.phase, Phase::Idle) {
+                    self.neutral();
+                    self.set_phase(Phase::Idle, "Aborted");
+                }
+            }
+            Message::Abort => {
+                if !matches!(self.model.phase, Phase::Idle) {
+                    self.set_phase(Phase::Idle, "Aborted");
+                }
+            }
+            Message::Tick => match self.model.phase {
+                Phase::Hold(until) if std::time::Instant::now() >= until => {
+                    let setpoint = self.setpoint(self.to.get_value());
+                    self.model.relm.stream().emit(Message::SetPoint(setpoint));
+                    let until = std::time::Instant::now()
+                        + std::time::Duration::from_secs_f64(self.record.get_value());
+                    self.set_phase(Phase::Record(until, None), "Recording step response");
+                }
+                Phase::Record(until, step_timestamp) if std::time::Instant::now() >= until => {
+                    self.finish(step_timestamp)
+                }
+                _ => (),
+            },
+            Message::SetPoint(_) => (),
+            Message::Annotations(_) => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Step Response"));
+        let root_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        root.add(&root_box);
+
+        // Settings of the step. They are locked while the experiment runs.
+        let settings = gtk::Grid::new();
+        settings.set_column_spacing(5);
+        root_box.add(&settings);
+
+        let label_axis = gtk::Label::new(Some("Axis"));
+        label_axis.set_xalign(0.0);
+        settings.attach(&label_axis, 0, 0, 1, 1);
+        let axis = gtk::ComboBoxText::new();
+        for &name in AXES.iter() {
+            axis.append_text(name);
+        }
+        axis.set_active(Some(ROLL as u32));
+        settings.attach(&axis, 1, 0, 1, 1);
+
+        let add_setting = |row: i32, label: &str, adjustment: &gtk::Adjustment, digits: u32| {
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            settings.attach(&label, 0, row, 1, 1);
+            settings.attach(
+                &gtk::SpinButton::new(Some(adjustment), 0.1, digits),
+                1,
+                row,
+                1,
+                1,
+            );
+        };
+        let from = gtk::Adjustment::new(0.0, -30.0, 30.0, 1.0, 5.0, 0.0);
+        add_setting(1, "From [°]", &from, 1);
+        let to = gtk::Adjustment::new(10.0, -30.0, 30.0, 1.0, 5.0, 0.0);
+        add_setting(2, "To [°]", &to, 1);
+        let throttle = gtk::Adjustment::new(30.0, 0.0, 100.0, 1.0, 10.0, 0.0);
+        add_setting(3, "Throttle [%]", &throttle, 0);
+        let hold = gtk::Adjustment::new(2.0, 0.0, 30.0, 0.5, 1.0, 0.0);
+        add_setting(4, "Hold Before [s]", &hold, 1);
+        let record = gtk::Adjustment::new(3.0, 0.5, 30.0, 0.5, 1.0, 0.0);
+        add_setting(5, "Record [s]", &record, 1);
+        let tolerance = gtk::Adjustment::new(5.0, 1.0, 20.0, 1.0, 5.0, 0.0);
+        add_setting(6, "Settling Band [%]", &tolerance, 0);
+
+        let box_run = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        root_box.add(&box_run);
+        let btn_run = gtk::Button::new();
+        btn_run.set_label("Run Step");
+        box_run.add(&btn_run);
+        let btn_abort = gtk::Button::new();
+        btn_abort.set_label("Abort");
+        box_run.add(&btn_abort);
+        let label_state = gtk::Label::new(Some(""));
+        box_run.add(&label_state);
+
+        // Tuning history
+        let history = gtk::ListStore::new(&[glib::Type::String; 7]);
+        let tree_view = gtk::TreeView::with_model(&history);
+        for (column, &title) in HISTORY_COLUMNS.iter().enumerate() {
+            let renderer = gtk::CellRendererText::new();
+            let tree_column = gtk::TreeViewColumn::new();
+            tree_column.set_title(title);
+            tree_column.pack_start(&renderer, true);
+            tree_column.add_attribute(&renderer, "text", column as i32);
+            tree_view.append_column(&tree_column);
+        }
+        let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled.set_size_request(-1, 100);
+        scrolled.add(&tree_view);
+        root_box.pack_start(&scrolled, true, true, 0);
+
+        connect!(relm, btn_run, connect_clicked(_), Message::Run);
+        connect!(relm, btn_abort, connect_clicked(_), Message::Stop);
+
+        Self {
+            model,
+            root,
+            axis,
+            from,
+            to,
+            throttle,
+            hold,
+            record,
+            tolerance,
+            settings,
+            label_state,
+            history,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_response() {
+        let samples: Vec<_> = (0..100).map(|i| (i as f64 * 0.01, 0.0)).collect();
+        let metrics = step_metrics(&samples, 0.0, 10.0, 0.02);
+        assert!(metrics.rise.is_none());
+        assert_eq!(metrics.overshoot.0, 0.0);
+        assert!(metrics.settling.is_none());
+        assert!((metrics.steady_state_error - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn first_order_step() {
+        let tau = 0.1;
+        let samples: Vec<_> = (0..=1000)
+            .map(|i| {
+                let time = i as f64 * 0.001;
+                (time, 10.0 * (1.0 - (-time / tau).exp()))
+            })
+            .collect();
+        let metrics = step_metrics(&samples, 0.0, 10.0, 0.02);
+
+        let (start, end) = metrics.rise.unwrap();
+        assert!((start - tau * (1.0f64 / 0.9).ln()).abs() < 0.002);
+        assert!((end - tau * 10.0f64.ln()).abs() < 0.002);
+        assert_eq!(metrics.overshoot.0, 0.0);
+        assert!((metrics.settling.unwrap() - tau * 50.0f64.ln()).abs() < 0.002);
+        assert!(metrics.steady_state_error.abs() < 0.01);
+    }
+}
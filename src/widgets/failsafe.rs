@@ -0,0 +1,185 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+pub struct Model {
+    relm: Relm<Widget>,
+    started: std::time::Instant,
+    armed: bool,
+    /// Time since the tilt is above the limit
+    exceeded_since: Option<std::time::Instant>,
+    tripped: bool,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Attitude(f32, f32),
+    Armed(bool),
+    Acknowledge,
+    Trip,
+    Acknowledged,
+}
+
+/// Disables the motors when roll or pitch stay above a limit while armed.
+/// Arming stays blocked until the operator acknowledges the trip.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    enable: gtk::CheckButton,
+    max_tilt: gtk::Adjustment,
+    duration: gtk::Adjustment,
+    label_state: gtk::Label,
+    btn_acknowledge: gtk::Button,
+    log: gtk::TextBuffer,
+}
+
+impl Widget {
+    /// Add a line with the time since the start to the event log
+    fn log(&self, text: &str) {
+        let line = format!(
+            "[{:8.1} s] {}\n",
+            self.model.started.elapsed().as_secs_f32(),
+            text
+        );
+        self.log.insert(&mut self.log.get_end_iter(), &line);
+    }
+
+    fn check_tilt(&mut self, roll: f32, pitch: f32) {
+        if !self.enable.get_active() || !self.model.armed || self.model.tripped {
+            self.model.exceeded_since = None;
+            return;
+        }
+        let max_tilt = self.max_tilt.get_value() as f32;
+        if roll.abs() <= max_tilt && pitch.abs() <= max_tilt {
+            self.model.exceeded_since = None;
+            return;
+        }
+        let since = *self
+            .model
+            .exceeded_since
+            .get_or_insert_with(std::time::Instant::now);
+        if since.elapsed().as_secs_f64() >= self.duration.get_value() {
+            self.model.tripped = true;
+            self.model.exceeded_since = None;
+            self.log(&format!(
+                "Tilt failsafe: roll {:.1}°, pitch {:.1}° above {:.1}° for {:.2} s, motors disabled",
+                roll,
+                pitch,
+                max_tilt,
+                since.elapsed().as_secs_f32()
+            ));
+            self.label_state
+                .set_markup("<span foreground=\"red\">TRIPPED</span>");
+            self.btn_acknowledge.set_sensitive(true);
+            self.model.relm.stream().emit(Message::Trip);
+        }
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        Model {
+            relm: relm.clone(),
+            started: std::time::Instant::now(),
+            armed: false,
+            exceeded_since: None,
+            tripped: false,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Attitude(roll, pitch) => self.check_tilt(roll, pitch),
+            Message::Armed(armed) => {
+                self.model.armed = armed;
+                self.model.exceeded_since = None;
+            }
+            Message::Acknowledge => {
+                if self.model.tripped {
+                    self.model.tripped = false;
+                    self.log("Tilt failsafe acknowledged");
+                    self.label_state.set_text("OK");
+                    self.btn_acknowledge.set_sensitive(false);
+                    self.model.relm.stream().emit(Message::Acknowledged);
+                }
+            }
+            Message::Trip => (),
+            Message::Acknowledged => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Tilt Failsafe"));
+        let grid = gtk::Grid::new();
+        grid.set_column_spacing(5);
+        grid.set_row_spacing(5);
+        root.add(&grid);
+
+        let enable = gtk::CheckButton::with_label("Enable");
+        enable.set_active(true);
+        grid.attach(&enable, 0, 0, 1, 1);
+        let label_state = gtk::Label::new(Some("OK"));
+        grid.attach(&label_state, 1, 0, 1, 1);
+
+        let label_tilt = gtk::Label::new(Some("Max Tilt [°]"));
+        label_tilt.set_xalign(0.0);
+        grid.attach(&label_tilt, 0, 1, 1, 1);
+        let max_tilt = gtk::Adjustment::new(45.0, 5.0, 90.0, 1.0, 10.0, 0.0);
+        grid.attach(&gtk::SpinButton::new(Some(&max_tilt), 1.0, 0), 1, 1, 1, 1);
+
+        let label_duration = gtk::Label::new(Some("Duration [s]"));
+        label_duration.set_xalign(0.0);
+        grid.attach(&label_duration, 0, 2, 1, 1);
+        let duration = gtk::Adjustment::new(0.3, 0.0, 5.0, 0.1, 1.0, 0.0);
+        grid.attach(&gtk::SpinButton::new(Some(&duration), 0.1, 2), 1, 2, 1, 1);
+
+        let btn_acknowledge = gtk::Button::new();
+        btn_acknowledge.set_label("Acknowledge");
+        btn_acknowledge.set_sensitive(false);
+        grid.attach(&btn_acknowledge, 0, 3, 2, 1);
+
+        // Event log
+        let log = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
+        let log_view = gtk::TextView::with_buffer(&log);
+        log_view.set_editable(false);
+        log_view.set_cursor_visible(false);
+        let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled.set_size_request(-1, 60);
+        scrolled.set_hexpand(true);
+        scrolled.add(&log_view);
+        grid.attach(&scrolled, 0, 4, 2, 1);
+
+        connect!(
+            relm,
+            btn_acknowledge,
+            connect_clicked(_),
+            Message::Acknowledge
+        );
+
+        Self {
+            model,
+            root,
+            enable,
+            max_tilt,
+            duration,
+            label_state,
+            btn_acknowledge,
+            log,
+        }
+    }
+}
@@ -6,37 +6,363 @@ use relm_derive::Msg;
 use gtk::prelude::*;
 use gtk::DrawingArea;
 
+use crate::widgets::connection::TIMESTAMP_RESOLUTION;
+
+mod decimate;
+
+/// Selectable time windows in s. `None` keeps the whole session.
+const TIME_WINDOWS: [(&str, Option<f64>); 4] = [
+    ("5 s", Some(5.0)),
+    ("30 s", Some(30.0)),
+    ("5 min", Some(300.0)),
+    ("Session", None),
+];
+const DEFAULT_TIME_WINDOW: u32 = 1;
+
+/// Zoom factor of one step of the mouse wheel
+const ZOOM_STEP: f64 = 0.8;
+
+/// Minimum size of a zoom box in pixels. Smaller boxes are ignored.
+const MIN_ZOOM_BOX: f64 = 5.0;
+
+/// Names and colors of the measurement cursors
+const CURSORS: [(&str, (f64, f64, f64)); 2] = [("A", (0.0, 1.0, 1.0)), ("B", (1.0, 0.0, 1.0))];
+
+/// Maximum number of ticks on the x and y axis
+const X_TICKS: usize = 10;
+const Y_TICKS: usize = 8;
+
+/// Round a range to 1, 2, 5 or 10 times a power of ten
+fn nice_number(range: f64, round: bool) -> f64 {
+    let exponent = range.log10().floor();
+    let fraction = range / 10f64.powf(exponent);
+    let nice_fraction = if round {
+        if fraction < 1.5 {
+            1.0
+        } else if fraction < 3.0 {
+            2.0
+        } else if fraction < 7.0 {
+            5.0
+        } else {
+            10.0
+        }
+    } else if fraction <= 1.0 {
+        1.0
+    } else if fraction <= 2.0 {
+        2.0
+    } else if fraction <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice_fraction * 10f64.powf(exponent)
+}
+
+/// Ticks at nice numbers between min and max
+fn ticks(min: f64, max: f64, count: usize) -> Vec<f64> {
+    if max <= min || !(max - min).is_finite() {
+        return Vec::new();
+    }
+    let range = nice_number(max - min, false);
+    let step = nice_number(range / (count.max(2) - 1) as f64, true);
+    let mut ticks = Vec::new();
+    let mut tick = (min / step).ceil() * step;
+    while tick <= max + step * 1e-9 {
+        // Avoid -0.0 and rounding noise in the labels
+        ticks.push(if tick.abs() < step * 1e-9 { 0.0 } else { tick });
+        tick += step;
+    }
+    ticks
+}
+
+/// Number of decimal places that are needed to show the ticks
+fn tick_digits(ticks: &[f64]) -> usize {
+    match ticks {
+        [first, second, ..] => (-(second - first).log10().floor()).max(0.0) as usize,
+        _ => 0,
+    }
+}
+
 #[derive(Debug)]
 pub struct DataPoint {
     x: f64,
     y: f64,
 }
 
+/// Linear interpolation of the data at x. `None` outside of the data.
+fn interpolate(data: &std::collections::VecDeque<DataPoint>, x: f64) -> Option<f64> {
+    let index = data.partition_point(|point| point.x < x);
+    let after = data.get(index)?;
+    if after.x <= x || index == 0 {
+        return if after.x == x { Some(after.y) } else { None };
+    }
+    let before = &data[index - 1];
+    Some(before.y + (after.y - before.y) * (x - before.x) / (after.x - before.x))
+}
+
+/// Draw lines of text on a dark box. The box is moved left and up to stay within the width and height.
+fn draw_text_box(cx: &cairo::Context, x: f64, y: f64, bounds: (f64, f64), lines: &[String]) {
+    const ROW_HEIGHT: f64 = 16.0;
+
+    let width = lines
+        .iter()
+        .map(|line| cx.text_extents(line).x_advance)
+        .fold(0.0, f64::max)
+        + 8.0;
+    let height = ROW_HEIGHT * lines.len() as f64 + 4.0;
+    let x = x.min(bounds.0 - width).max(0.0);
+    let y = y.min(bounds.1 - height).max(0.0);
+
+    cx.set_source_rgba(0.2, 0.2, 0.2, 0.8);
+    cx.rectangle(x, y, width, height);
+    cx.fill();
+    cx.set_source_rgb(1.0, 1.0, 1.0);
+    for (index, line) in lines.iter().enumerate() {
+        cx.move_to(x + 4.0, y + ROW_HEIGHT * (index + 1) as f64 - 2.0);
+        cx.show_text(line);
+    }
+}
+
 struct DataSeries {
-    data: Vec<DataPoint>,
+    data: std::collections::VecDeque<DataPoint>,
     color: (f64, f64, f64),
-    _label: String,
+    label: String,
+    unit: String,
+    target: Option<f64>,
+    visible: bool,
+}
+
+/// Marks drawn on top of the data, e.g. the results of an experiment.
+/// The x positions are device timestamps, the y positions in the units of the data.
+#[derive(Clone, Debug)]
+pub enum Annotation {
+    /// Vertical line at a x value with a label
+    Vertical(f64, String),
+    /// Horizontal band between two y values
+    Band(f64, f64),
+    /// Point with a label
+    Point(f64, f64, String),
+}
+
+/// Mouse drag on the chart
+#[derive(Clone, Copy, Debug)]
+enum Drag {
+    /// Move the shown range. Position where the drag started and the range at that time.
+    Pan((f64, f64), Range),
+    /// Zoom into a box from the start to the current position
+    Zoom((f64, f64), (f64, f64)),
 }
 
+/// Shown range as x min, x max, y min and y max
+type Range = (f64, f64, f64, f64);
+
 pub struct Model {
     draw_handler: DrawHandler<DrawingArea>,
     data: Vec<DataSeries>,
     min_max: Option<(f64, f64)>,
+    annotations: Vec<Annotation>,
+    /// Device timestamp of the first sample after the last clear. The time axis starts there.
+    time_origin: Option<u32>,
+    /// Length of the shown and stored history in s
+    time_window: Option<f64>,
+    /// Area of each legend entry as x, y, width and height for the clicks
+    legend: Vec<(f64, f64, f64, f64)>,
+    /// Fixed range while paused. `None` follows the latest data with auto scaling.
+    view: Option<Range>,
+    /// Range and area of the chart as x, y, width and height of the last draw
+    range: Range,
+    chart: (f64, f64, f64, f64),
+    drag: Option<Drag>,
+    /// Position of the mouse over the drawing area
+    hover: Option<(f64, f64)>,
+    /// Time of the measurement cursors and the cursor that is placed next
+    cursors: [Option<f64>; 2],
+    next_cursor: usize,
 }
 
 #[derive(Msg)]
 pub enum Message {
     Draw,
     AddAngle(u32, f32, f32, f32),
+    SetTarget(Option<(f32, f32, f32)>),
+    SetAnnotations(Vec<Annotation>),
     Clear,
+    ButtonPress(f64, f64, u32, gdk::EventType),
+    ButtonRelease,
+    Motion(f64, f64),
+    Leave,
+    ClearCursors,
+    Scroll(f64, f64, gdk::ScrollDirection, gdk::ModifierType),
+    PauseToggled,
+    Live,
+    TimeWindowChanged,
 }
 
 pub struct Widget {
     model: Model,
+    root: gtk::Box,
     drawing_area: DrawingArea,
+    time_window: gtk::ComboBoxText,
+    pause: gtk::ToggleButton,
 }
 
 impl Widget {
+    /// Time since connect in s of a device timestamp. The timestamp wraps like the `u32` counter of the device.
+    fn time(&self, timestamp: f64) -> f64 {
+        let origin = self.model.time_origin.unwrap_or(0);
+        let ticks = timestamp.floor();
+        let elapsed = (ticks as i64 as u32).wrapping_sub(origin);
+        (elapsed as f64 + timestamp - ticks) * TIMESTAMP_RESOLUTION
+    }
+
+    /// Remove the samples that are older than the time window.
+    /// Nothing is removed while paused, so the shown data stays available.
+    fn trim_history(&mut self) {
+        if self.model.view.is_some() {
+            return;
+        }
+        let window = match self.model.time_window {
+            Some(window) => window,
+            None => return,
+        };
+        for series in self.model.data.iter_mut() {
+            let latest = match series.data.back() {
+                Some(point) => point.x,
+                None => continue,
+            };
+            while series
+                .data
+                .front()
+                .map_or(false, |point| point.x < latest - window)
+            {
+                series.data.pop_front();
+            }
+        }
+    }
+
+    /// Value at a position in the drawing area with the range of the last draw
+    fn value_at(&self, position_x: f64, position_y: f64) -> (f64, f64) {
+        let (x_min, x_max, y_min, y_max) = self.model.range;
+        let (x, y, w, h) = self.model.chart;
+        (
+            x_min + (position_x - x) / w * (x_max - x_min),
+            y_max - (position_y - y) / h * (y_max - y_min),
+        )
+    }
+
+    /// Show a fixed range. The display stays paused until live mode is selected again.
+    /// A range that is not finite is ignored.
+    fn set_view(&mut self, range: Range) {
+        let (x_min, x_max, y_min, y_max) = range;
+        if ![x_min, x_max, y_min, y_max]
+            .iter()
+            .all(|value| value.is_finite())
+        {
+            return;
+        }
+        self.model.view = Some(range);
+        if !self.pause.get_active() {
+            self.pause.set_active(true);
+        }
+    }
+
+    /// Index of the legend entry at a position
+    fn legend_entry(&self, x: f64, y: f64) -> Option<usize> {
+        self.model
+            .legend
+            .iter()
+            .position(|&(left, top, width, height)| {
+                x >= left && x <= left + width && y >= top && y <= top + height
+            })
+    }
+
+    fn button_press(&mut self, x: f64, y: f64, button: u32, event_type: gdk::EventType) {
+        let legend_entry = self.legend_entry(x, y);
+        match (event_type, button, legend_entry) {
+            (gdk::EventType::DoubleButtonPress, _, None) => {
+                self.model.drag = None;
+                self.pause.set_active(false);
+            }
+            (gdk::EventType::ButtonPress, 1, Some(index)) => {
+                // Toggle the series of the clicked legend entry
+                self.model.data[index].visible = !self.model.data[index].visible;
+            }
+            (gdk::EventType::ButtonPress, 1, None) => {
+                self.model.drag = Some(Drag::Pan((x, y), self.model.range));
+            }
+            (gdk::EventType::ButtonPress, 2, None) => {
+                // Place the measurement cursors in turns
+                let (time, _) = self.value_at(x, y);
+                self.model.cursors[self.model.next_cursor] = Some(time);
+                self.model.next_cursor = (self.model.next_cursor + 1) % CURSORS.len();
+            }
+            (gdk::EventType::ButtonPress, 3, None) => {
+                self.model.drag = Some(Drag::Zoom((x, y), (x, y)));
+            }
+            _ => (),
+        }
+    }
+
+    fn motion(&mut self, x: f64, y: f64) {
+        self.model.hover = Some((x, y));
+        match self.model.drag {
+            Some(Drag::Pan(start, (x_min, x_max, y_min, y_max))) => {
+                let (_, _, w, h) = self.model.chart;
+                let dx = (x - start.0) / w * (x_max - x_min);
+                let dy = (y - start.1) / h * (y_max - y_min);
+                if dx != 0.0 || dy != 0.0 {
+                    self.set_view((x_min - dx, x_max - dx, y_min + dy, y_max + dy));
+                }
+            }
+            Some(Drag::Zoom(start, _)) => self.model.drag = Some(Drag::Zoom(start, (x, y))),
+            None => (),
+        }
+    }
+
+    fn button_release(&mut self) {
+        if let Some(Drag::Zoom(start, end)) = self.model.drag {
+            if (end.0 - start.0).abs() >= MIN_ZOOM_BOX && (end.1 - start.1).abs() >= MIN_ZOOM_BOX {
+                let (x_start, y_start) = self.value_at(start.0, start.1);
+                let (x_end, y_end) = self.value_at(end.0, end.1);
+                self.set_view((
+                    x_start.min(x_end),
+                    x_start.max(x_end),
+                    y_start.min(y_end),
+                    y_start.max(y_end),
+                ));
+            }
+        }
+        self.model.drag = None;
+    }
+
+    /// Zoom around the position of the mouse. Shift zooms only in x, Control only in y.
+    fn scroll(
+        &mut self,
+        x: f64,
+        y: f64,
+        direction: gdk::ScrollDirection,
+        state: gdk::ModifierType,
+    ) {
+        let factor = match direction {
+            gdk::ScrollDirection::Up => ZOOM_STEP,
+            gdk::ScrollDirection::Down => 1.0 / ZOOM_STEP,
+            _ => return,
+        };
+        let zoom_x = !state.contains(gdk::ModifierType::CONTROL_MASK);
+        let zoom_y = !state.contains(gdk::ModifierType::SHIFT_MASK);
+        let (center_x, center_y) = self.value_at(x, y);
+        let (mut x_min, mut x_max, mut y_min, mut y_max) = self.model.range;
+        if zoom_x {
+            x_min = center_x - (center_x - x_min) * factor;
+            x_max = center_x + (x_max - center_x) * factor;
+        }
+        if zoom_y {
+            y_min = center_y - (center_y - y_min) * factor;
+            y_max = center_y + (y_max - center_y) * factor;
+        }
+        self.set_view((x_min, x_max, y_min, y_max));
+    }
+
     fn draw_background(&mut self, width: i32, height: i32) {
         let cx = self.model.draw_handler.get_context();
 
@@ -44,28 +370,9 @@ impl Widget {
         cx.paint();
 
         let w = width as f64 * 0.85;
-        let h = height as f64 * 0.85;
+        let h = height as f64 * 0.8;
         let x = width as f64 * 0.1;
         let y = height as f64 * 0.05;
-        // Draw Background of the chart
-        {
-            // Black Background
-            cx.rectangle(x, y, w, h);
-            cx.set_source_rgb(0.0, 0.0, 0.0);
-            cx.fill();
-            // Devide in 10 parts in x and y direction
-            for i in 0..12 {
-                cx.set_line_width(1.0);
-                cx.move_to(x + w / 11.0 * i as f64, y);
-                cx.line_to(x + w / 11.0 * i as f64, y + h);
-                cx.set_source_rgb(0.5, 0.5, 0.5);
-                cx.stroke();
-                cx.move_to(x, y + h / 11.0 * i as f64);
-                cx.line_to(x + w, y + h / 11.0 * i as f64);
-                cx.set_source_rgb(0.5, 0.5, 0.5);
-                cx.stroke();
-            }
-        }
         // Draw the data
         {
             // Find x min, x max, y min, y max
@@ -78,7 +385,7 @@ impl Widget {
                 x_max = std::f64::MIN;
                 y_min = min;
                 y_max = max;
-                for series in self.model.data.iter() {
+                for series in self.model.data.iter().filter(|series| series.visible) {
                     for point in series.data.iter() {
                         x_min = x_min.min(point.x);
                         x_max = x_max.max(point.x);
@@ -89,15 +396,42 @@ impl Widget {
                 x_max = std::f64::MIN;
                 y_min = std::f64::MAX;
                 y_max = std::f64::MIN;
-                for series in self.model.data.iter() {
+                for series in self.model.data.iter().filter(|series| series.visible) {
                     for point in series.data.iter() {
                         x_min = x_min.min(point.x);
                         x_max = x_max.max(point.x);
                         y_min = y_min.min(point.y);
                         y_max = y_max.max(point.y);
                     }
+                    // Keep the target visible
+                    if let Some(target) = series.target {
+                        y_min = y_min.min(target);
+                        y_max = y_max.max(target);
+                    }
+                }
+            }
+            // Without visible data there is nothing to scale to, so a finite default range is used
+            if x_min > x_max {
+                x_min = 0.0;
+                x_max = 0.0;
+            }
+            if y_min > y_max {
+                y_min = -1.0;
+                y_max = 1.0;
+            }
+            // Show the full time window even if it is not filled yet
+            if let Some(window) = self.model.time_window {
+                if x_max >= x_min {
+                    x_min = x_max - window;
                 }
             }
+            // A paused display keeps its range
+            if let Some(view) = self.model.view {
+                x_min = view.0;
+                x_max = view.1;
+                y_min = view.2;
+                y_max = view.3;
+            }
             // Check if min==max
             if (x_max - x_min).abs() <= f64::EPSILON {
                 x_max += 0.5;
@@ -112,25 +446,275 @@ impl Widget {
             let x_scaling = w / (x_max - x_min);
             let y_offset = -y_min;
             let y_scaling = h / (y_max - y_min);
+            self.model.range = (x_min, x_max, y_min, y_max);
+            self.model.chart = (x, y, w, h);
+
+            // Draw Background of the chart
+            {
+                // Black Background
+                cx.rectangle(x, y, w, h);
+                cx.set_source_rgb(0.0, 0.0, 0.0);
+                cx.fill();
+
+                // Grid lines with labels at the ticks
+                cx.set_line_width(1.0);
+                let x_ticks = ticks(x_min, x_max, X_TICKS);
+                let x_digits = tick_digits(&x_ticks);
+                for &tick in x_ticks.iter() {
+                    let tick_x = x + (x_offset + tick) * x_scaling;
+                    cx.set_source_rgb(0.5, 0.5, 0.5);
+                    cx.move_to(tick_x, y);
+                    cx.line_to(tick_x, y + h);
+                    cx.stroke();
+                    let label = format!("{:.*}", x_digits, tick);
+                    let extents = cx.text_extents(&label);
+                    cx.set_source_rgb(0.0, 0.0, 0.0);
+                    cx.move_to(tick_x - extents.x_advance / 2.0, y + h + 14.0);
+                    cx.show_text(&label);
+                }
+                let y_ticks = ticks(y_min, y_max, Y_TICKS);
+                let y_digits = tick_digits(&y_ticks);
+                for &tick in y_ticks.iter() {
+                    let tick_y = y + h - (y_offset + tick) * y_scaling;
+                    cx.set_source_rgb(0.5, 0.5, 0.5);
+                    cx.move_to(x, tick_y);
+                    cx.line_to(x + w, tick_y);
+                    cx.stroke();
+                    let label = format!("{:.*}", y_digits, tick);
+                    let extents = cx.text_extents(&label);
+                    cx.set_source_rgb(0.0, 0.0, 0.0);
+                    cx.move_to(x - extents.x_advance - 5.0, tick_y + 4.0);
+                    cx.show_text(&label);
+                }
+
+                // Axis titles
+                cx.set_source_rgb(0.0, 0.0, 0.0);
+                let x_title = "Time since connect [s]";
+                let extents = cx.text_extents(x_title);
+                cx.move_to(x + w - extents.x_advance, y + h + 30.0);
+                cx.show_text(x_title);
+                let mut units: Vec<&str> = Vec::new();
+                for series in self.model.data.iter().filter(|series| series.visible) {
+                    if !units.contains(&series.unit.as_str()) {
+                        units.push(&series.unit);
+                    }
+                }
+                if !units.is_empty() {
+                    cx.move_to(4.0, y - 4.0);
+                    cx.show_text(&format!("[{}]", units.join(", ")));
+                }
+            }
 
             // Draw the data
-            for series in self.model.data.iter() {
+            cx.save();
+            cx.rectangle(x, y, w, h);
+            cx.clip();
+            for series in self.model.data.iter().filter(|series| series.visible) {
                 cx.set_line_width(2.0);
                 cx.set_source_rgb(series.color.0, series.color.1, series.color.2);
-                if !series.data.is_empty() {
-                    cx.move_to(
-                        x + (x_offset + series.data[0].x) * x_scaling,
-                        y + h - (y_offset + series.data[0].y.min(y_max).max(y_min)) * y_scaling,
-                    );
-                }
-                for point in series.data.iter().skip(1) {
-                    cx.line_to(
-                        x + (x_offset + point.x) * x_scaling,
-                        y + h - (y_offset + point.y.min(y_max).max(y_min)) * y_scaling,
-                    );
+                // Only the points that make a difference for each pixel column are drawn
+                let points = decimate::min_max_decimate(
+                    series.data.iter().map(|point| (point.x, point.y)),
+                    x_min,
+                    x_max,
+                    w as usize,
+                );
+                for (index, &(point_x, point_y)) in points.iter().enumerate() {
+                    let point_x = x + (x_offset + point_x) * x_scaling;
+                    let point_y = y + h - (y_offset + point_y.min(y_max).max(y_min)) * y_scaling;
+                    if index == 0 {
+                        cx.move_to(point_x, point_y);
+                    } else {
+                        cx.line_to(point_x, point_y);
+                    }
                 }
                 cx.stroke();
             }
+
+            // Draw the targets as dashed lines
+            cx.set_dash(&[6.0, 4.0], 0.0);
+            for series in self.model.data.iter().filter(|series| series.visible) {
+                if let Some(target) = series.target {
+                    let target_y = y + h - (y_offset + target.min(y_max).max(y_min)) * y_scaling;
+                    cx.set_line_width(1.5);
+                    cx.set_source_rgb(series.color.0, series.color.1, series.color.2);
+                    cx.move_to(x, target_y);
+                    cx.line_to(x + w, target_y);
+                    cx.stroke();
+                }
+            }
+            cx.set_dash(&[], 0.0);
+
+            // Draw the annotations that are within the chart
+            cx.set_source_rgb(1.0, 1.0, 0.0);
+            cx.set_line_width(1.0);
+            let to_x = |value: f64| x + (x_offset + value) * x_scaling;
+            let to_y = |value: f64| y + h - (y_offset + value.min(y_max).max(y_min)) * y_scaling;
+            for annotation in self.model.annotations.iter() {
+                match annotation {
+                    Annotation::Vertical(value, label) => {
+                        if *value < x_min || *value > x_max {
+                            continue;
+                        }
+                        cx.move_to(to_x(*value), y);
+                        cx.line_to(to_x(*value), y + h);
+                        cx.stroke();
+                        cx.move_to(to_x(*value) + 3.0, y + 12.0);
+                        cx.show_text(label);
+                    }
+                    Annotation::Band(low, high) => {
+                        cx.set_source_rgba(1.0, 1.0, 0.0, 0.2);
+                        cx.rectangle(x, to_y(*high), w, to_y(*low) - to_y(*high));
+                        cx.fill();
+                        cx.set_source_rgb(1.0, 1.0, 0.0);
+                    }
+                    Annotation::Point(point_x, point_y, label) => {
+                        if *point_x < x_min || *point_x > x_max {
+                            continue;
+                        }
+                        cx.arc(
+                            to_x(*point_x),
+                            to_y(*point_y),
+                            4.0,
+                            0.0,
+                            2.0 * std::f64::consts::PI,
+                        );
+                        cx.fill();
+                        cx.move_to(to_x(*point_x) + 6.0, to_y(*point_y) - 6.0);
+                        cx.show_text(label);
+                    }
+                }
+            }
+
+            // Draw the measurement cursors
+            for (cursor, &(name, color)) in self.model.cursors.iter().zip(CURSORS.iter()) {
+                if let Some(time) = *cursor {
+                    cx.set_source_rgb(color.0, color.1, color.2);
+                    cx.move_to(to_x(time), y);
+                    cx.line_to(to_x(time), y + h);
+                    cx.stroke();
+                    cx.move_to(to_x(time) + 3.0, y + h - 4.0);
+                    cx.show_text(name);
+                }
+            }
+
+            // Draw the crosshair at the mouse
+            let hover = self.model.hover.filter(|&(hover_x, hover_y)| {
+                hover_x >= x && hover_x <= x + w && hover_y >= y && hover_y <= y + h
+            });
+            if let Some((hover_x, hover_y)) = hover {
+                cx.set_source_rgba(1.0, 1.0, 1.0, 0.6);
+                cx.set_dash(&[2.0, 2.0], 0.0);
+                cx.move_to(hover_x, y);
+                cx.line_to(hover_x, y + h);
+                cx.move_to(x, hover_y);
+                cx.line_to(x + w, hover_y);
+                cx.stroke();
+                cx.set_dash(&[], 0.0);
+            }
+
+            // Draw the box of a running box zoom
+            if let Some(Drag::Zoom(start, end)) = self.model.drag {
+                cx.set_source_rgba(1.0, 1.0, 1.0, 0.2);
+                cx.rectangle(start.0, start.1, end.0 - start.0, end.1 - start.1);
+                cx.fill_preserve();
+                cx.set_source_rgb(1.0, 1.0, 1.0);
+                cx.stroke();
+            }
+        }
+        cx.restore();
+        self.draw_legend(&cx, x + 8.0, y + 8.0);
+        self.draw_readouts(&cx, (width as f64, height as f64));
+    }
+
+    /// Draw the values at the crosshair next to the mouse and
+    /// the differences between the measurement cursors in the top right corner
+    fn draw_readouts(&self, cx: &cairo::Context, bounds: (f64, f64)) {
+        let (x, y, w, h) = self.model.chart;
+        let visible = || self.model.data.iter().filter(|series| series.visible);
+
+        let hover = self.model.hover.filter(|&(hover_x, hover_y)| {
+            hover_x >= x && hover_x <= x + w && hover_y >= y && hover_y <= y + h
+        });
+        if let Some((hover_x, hover_y)) = hover {
+            let (time, value) = self.value_at(hover_x, hover_y);
+            let mut lines = vec![format!("t: {:.3} s", time), format!("y: {:.2}", value)];
+            for series in visible() {
+                if let Some(value) = interpolate(&series.data, time) {
+                    lines.push(format!("{}: {:.2}", series.label, value));
+                }
+            }
+            draw_text_box(cx, hover_x + 12.0, hover_y + 12.0, bounds, &lines);
+        }
+
+        if let [Some(time_a), Some(time_b)] = self.model.cursors {
+            let delta = time_b - time_a;
+            let mut lines = vec![format!("Δt: {:.3} s", delta)];
+            if delta.abs() > f64::EPSILON {
+                lines.push(format!("f: {:.3} Hz", 1.0 / delta.abs()));
+            }
+            for series in visible() {
+                let values = (
+                    interpolate(&series.data, time_a),
+                    interpolate(&series.data, time_b),
+                );
+                if let (Some(value_a), Some(value_b)) = values {
+                    lines.push(format!("Δ{}: {:.2}", series.label, value_b - value_a));
+                }
+            }
+            draw_text_box(cx, x + w, y + 8.0, bounds, &lines);
+        }
+    }
+
+    /// Draw a legend with the color, the label and the latest value of each series.
+    /// Hidden series are drawn in gray.
+    fn draw_legend(&mut self, cx: &cairo::Context, x: f64, y: f64) {
+        const ROW_HEIGHT: f64 = 16.0;
+        const SWATCH: f64 = 10.0;
+
+        let entries: Vec<String> = self
+            .model
+            .data
+            .iter()
+            .map(|series| match series.data.back() {
+                Some(point) => format!("{}: {:.1}", series.label, point.y),
+                None => series.label.clone(),
+            })
+            .collect();
+        let width = entries
+            .iter()
+            .map(|entry| cx.text_extents(entry).x_advance)
+            .fold(0.0, f64::max)
+            + SWATCH
+            + 16.0;
+        let height = ROW_HEIGHT * entries.len() as f64 + 4.0;
+
+        cx.set_source_rgba(0.2, 0.2, 0.2, 0.8);
+        cx.rectangle(x, y, width, height);
+        cx.fill();
+
+        self.model.legend.clear();
+        for (index, (series, entry)) in self.model.data.iter().zip(entries.iter()).enumerate() {
+            let row_y = y + 2.0 + ROW_HEIGHT * index as f64;
+            let color = if series.visible {
+                series.color
+            } else {
+                (0.5, 0.5, 0.5)
+            };
+            cx.set_source_rgb(color.0, color.1, color.2);
+            cx.rectangle(x + 4.0, row_y + (ROW_HEIGHT - SWATCH) / 2.0, SWATCH, SWATCH);
+            if series.visible {
+                cx.fill();
+            } else {
+                cx.set_line_width(1.0);
+                cx.stroke();
+            }
+            if series.visible {
+                cx.set_source_rgb(1.0, 1.0, 1.0);
+            }
+            cx.move_to(x + SWATCH + 10.0, row_y + ROW_HEIGHT - 4.0);
+            cx.show_text(entry);
+            self.model.legend.push((x, row_y, width, ROW_HEIGHT));
         }
     }
 }
@@ -147,25 +731,45 @@ impl relm::Update for Widget {
         let mut data: Vec<DataSeries> = Vec::new();
 
         data.push(DataSeries {
-            data: Vec::new(),
+            data: std::collections::VecDeque::new(),
             color: (1.0, 0.0, 0.0),
-            _label: "Roll [°]".to_string(),
+            label: "Roll [°]".to_string(),
+            unit: "°".to_string(),
+            target: None,
+            visible: true,
         });
         data.push(DataSeries {
-            data: Vec::new(),
+            data: std::collections::VecDeque::new(),
             color: (0.0, 1.0, 0.0),
-            _label: "Pitch [°]".to_string(),
+            label: "Pitch [°]".to_string(),
+            unit: "°".to_string(),
+            target: None,
+            visible: true,
         });
         data.push(DataSeries {
-            data: Vec::new(),
+            data: std::collections::VecDeque::new(),
             color: (0.0, 0.0, 1.0),
-            _label: "Yaw[°]".to_string(),
+            label: "Yaw [°]".to_string(),
+            unit: "°".to_string(),
+            target: None,
+            visible: true,
         });
 
         Self::Model {
             draw_handler,
             data,
             min_max: None,
+            annotations: Vec::new(),
+            time_origin: None,
+            time_window: TIME_WINDOWS[DEFAULT_TIME_WINDOW as usize].1,
+            legend: Vec::new(),
+            view: None,
+            range: (0.0, 1.0, 0.0, 1.0),
+            chart: (0.0, 0.0, 1.0, 1.0),
+            drag: None,
+            hover: None,
+            cursors: [None; 2],
+            next_cursor: 0,
         }
     }
 
@@ -181,42 +785,115 @@ impl relm::Update for Widget {
                 for data in self.model.data.iter_mut() {
                     data.data.clear();
                 }
+                self.model.annotations.clear();
+                self.model.time_origin = None;
+                self.model.cursors = [None; 2];
+                self.model.next_cursor = 0;
+            }
+            Message::SetAnnotations(annotations) => {
+                self.model.annotations = annotations
+                    .into_iter()
+                    .map(|annotation| match annotation {
+                        Annotation::Vertical(x, label) => Annotation::Vertical(self.time(x), label),
+                        Annotation::Point(x, y, label) => Annotation::Point(self.time(x), y, label),
+                        band => band,
+                    })
+                    .collect();
             }
-            Message::AddAngle(time, roll, pitch, yaw) => {
-                self.model.data[0].data.push(DataPoint {
-                    x: time as f64,
+            Message::ButtonPress(x, y, button, event_type) => {
+                self.button_press(x, y, button, event_type)
+            }
+            Message::ButtonRelease => self.button_release(),
+            Message::Motion(x, y) => self.motion(x, y),
+            Message::Leave => self.model.hover = None,
+            Message::ClearCursors => {
+                self.model.cursors = [None; 2];
+                self.model.next_cursor = 0;
+            }
+            Message::Scroll(x, y, direction, state) => self.scroll(x, y, direction, state),
+            Message::PauseToggled => {
+                if self.pause.get_active() {
+                    if self.model.view.is_none() {
+                        self.model.view = Some(self.model.range);
+                    }
+                } else {
+                    self.model.view = None;
+                    self.trim_history();
+                }
+            }
+            Message::Live => self.pause.set_active(false),
+            Message::SetTarget(target) => {
+                let target = target.map(|(roll, pitch, yaw)| [roll, pitch, yaw]);
+                for (index, series) in self.model.data.iter_mut().enumerate() {
+                    series.target = target.map(|target| target[index] as f64);
+                }
+            }
+            Message::AddAngle(timestamp, roll, pitch, yaw) => {
+                let origin = *self.model.time_origin.get_or_insert(timestamp);
+                let time = timestamp.wrapping_sub(origin) as f64 * TIMESTAMP_RESOLUTION;
+                self.model.data[0].data.push_back(DataPoint {
+                    x: time,
                     y: roll as f64,
                 });
-                self.model.data[1].data.push(DataPoint {
-                    x: time as f64,
+                self.model.data[1].data.push_back(DataPoint {
+                    x: time,
                     y: pitch as f64,
                 });
-                self.model.data[2].data.push(DataPoint {
-                    x: time as f64,
+                self.model.data[2].data.push_back(DataPoint {
+                    x: time,
                     y: yaw as f64,
                 });
-                if self.model.data[0].data.len() >= 200 {
-                    for data in self.model.data.iter_mut() {
-                        data.data.remove(0);
-                    }
-                }
+                self.trim_history();
+            }
+            Message::TimeWindowChanged => {
+                self.model.time_window = self
+                    .time_window
+                    .get_active()
+                    .and_then(|index| TIME_WINDOWS[index as usize].1);
+                self.trim_history();
             }
         }
     }
 }
 
 impl relm::Widget for Widget {
-    type Root = DrawingArea;
+    type Root = gtk::Box;
 
     fn root(&self) -> Self::Root {
-        self.drawing_area.clone()
+        self.root.clone()
     }
 
     fn view(relm: &Relm<Self>, mut model: Self::Model) -> Self {
+        let root = gtk::Box::new(gtk::Orientation::Vertical, 5);
+
+        // Settings of the shown history
+        let box_settings = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        root.add(&box_settings);
+        box_settings.add(&gtk::Label::new(Some("Time Window")));
+        let time_window = gtk::ComboBoxText::new();
+        for &(label, _) in TIME_WINDOWS.iter() {
+            time_window.append_text(label);
+        }
+        time_window.set_active(Some(DEFAULT_TIME_WINDOW));
+        box_settings.add(&time_window);
+        let pause = gtk::ToggleButton::with_label("Pause");
+        box_settings.add(&pause);
+        let btn_live = gtk::Button::new();
+        btn_live.set_label("Live");
+        box_settings.add(&btn_live);
+        let btn_clear_cursors = gtk::Button::new();
+        btn_clear_cursors.set_label("Clear Cursors");
+        btn_clear_cursors.set_tooltip_text(Some("Middle click places the cursors A and B"));
+        box_settings.add(&btn_clear_cursors);
+        pause.set_tooltip_text(Some(
+            "Wheel: zoom (Shift: time only, Ctrl: value only)\nDrag: pan\nRight drag: box zoom\nDouble click: live",
+        ));
+
         // Create the drawing area
         let drawing_area = DrawingArea::new();
         drawing_area.set_size_request(400, 300);
         model.draw_handler.init(&drawing_area);
+        root.pack_start(&drawing_area, true, true, 0);
 
         // Connect the draw event
         connect!(
@@ -226,9 +903,88 @@ impl relm::Widget for Widget {
             return (Some(Message::Draw), Inhibit(false))
         );
 
-        Self {
+        // Clicks on the legend, zoom and pan
+        drawing_area.add_events(
+            gdk::EventMask::BUTTON_PRESS_MASK
+                | gdk::EventMask::BUTTON_RELEASE_MASK
+                | gdk::EventMask::POINTER_MOTION_MASK
+                | gdk::EventMask::LEAVE_NOTIFY_MASK
+                | gdk::EventMask::SCROLL_MASK,
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_button_press_event(_, event),
+            return (
+                Some(Message::ButtonPress(
+                    event.get_position().0,
+                    event.get_position().1,
+                    event.get_button(),
+                    event.get_event_type()
+                )),
+                Inhibit(false)
+            )
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_button_release_event(_, _),
+            return (Some(Message::ButtonRelease), Inhibit(false))
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_motion_notify_event(_, event),
+            return (
+                Some(Message::Motion(
+                    event.get_position().0,
+                    event.get_position().1
+                )),
+                Inhibit(false)
+            )
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_scroll_event(_, event),
+            return (
+                Some(Message::Scroll(
+                    event.get_position().0,
+                    event.get_position().1,
+                    event.get_direction(),
+                    event.get_state()
+                )),
+                Inhibit(true)
+            )
+        );
+        connect!(relm, pause, connect_toggled(_), Message::PauseToggled);
+        connect!(relm, btn_live, connect_clicked(_), Message::Live);
+        connect!(
+            relm,
             drawing_area,
+            connect_leave_notify_event(_, _),
+            return (Some(Message::Leave), Inhibit(false))
+        );
+        connect!(
+            relm,
+            btn_clear_cursors,
+            connect_clicked(_),
+            Message::ClearCursors
+        );
+
+        connect!(
+            relm,
+            time_window,
+            connect_changed(_),
+            Message::TimeWindowChanged
+        );
+
+        Self {
             model,
+            root,
+            drawing_area,
+            time_window,
+            pause,
         }
     }
 }
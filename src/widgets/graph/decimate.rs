@@ -0,0 +1,123 @@
+/// Reduce a series to the points that are needed to draw it with `columns` pixel columns between `x_min` and `x_max`.
+/// Each column keeps its first, lowest, highest and last point in their original order,
+/// so spikes survive and the drawn line looks the same as with all points.
+/// The last point before and the first point after the range are kept to draw the line to the border.
+/// The points have to be sorted by x.
+pub fn min_max_decimate<I>(points: I, x_min: f64, x_max: f64, columns: usize) -> Vec<(f64, f64)>
+where
+    I: IntoIterator<Item = (f64, f64)>,
+{
+    let columns = columns.max(1);
+    let column_width = (x_max - x_min) / columns as f64;
+    let mut output = Vec::with_capacity(columns * 4 + 2);
+    let mut before = None;
+    // Column with its first, min, max and last point
+    let mut bucket: Option<(usize, [(f64, f64); 4])> = None;
+
+    let flush = |output: &mut Vec<(f64, f64)>, points: [(f64, f64); 4]| {
+        let [first, min, max, last] = points;
+        output.push(first);
+        // Keep the extremes in the order they occurred
+        let (a, b) = if min.0 <= max.0 {
+            (min, max)
+        } else {
+            (max, min)
+        };
+        for &point in [a, b, last].iter() {
+            if output.last() != Some(&point) {
+                output.push(point);
+            }
+        }
+    };
+
+    for point in points {
+        if point.0 < x_min {
+            before = Some(point);
+            continue;
+        }
+        if let Some(before) = before.take() {
+            output.push(before);
+        }
+        if point.0 > x_max {
+            if let Some((_, points)) = bucket.take() {
+                flush(&mut output, points);
+            }
+            output.push(point);
+            return output;
+        }
+
+        let column = if column_width > 0.0 {
+            (((point.0 - x_min) / column_width) as usize).min(columns - 1)
+        } else {
+            0
+        };
+        bucket = match bucket {
+            Some((current, mut points)) if current == column => {
+                if point.1 < points[1].1 {
+                    points[1] = point;
+                }
+                if point.1 > points[2].1 {
+                    points[2] = point;
+                }
+                points[3] = point;
+                Some((current, points))
+            }
+            previous => {
+                if let Some((_, points)) = previous {
+                    flush(&mut output, points);
+                }
+                Some((column, [point; 4]))
+            }
+        };
+    }
+    if let Some((_, points)) = bucket {
+        flush(&mut output, points);
+    }
+    // All points are before the range
+    if let Some(before) = before {
+        output.push(before);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_series() {
+        let points: Vec<_> = (0..1000).map(|i| (i as f64, 2.0)).collect();
+        let output = min_max_decimate(points, 0.0, 999.0, 10);
+        assert!(output.len() <= 20);
+        assert!(output.iter().all(|&(_, y)| y == 2.0));
+        assert_eq!(output.first(), Some(&(0.0, 2.0)));
+        assert_eq!(output.last(), Some(&(999.0, 2.0)));
+    }
+
+    #[test]
+    fn keeps_spikes_in_order() {
+        let mut points: Vec<_> = (0..1000).map(|i| (i as f64, 0.0)).collect();
+        points[500].1 = 10.0;
+        points[501].1 = -10.0;
+        let output = min_max_decimate(points, 0.0, 999.0, 10);
+        let high = output.iter().position(|&point| point == (500.0, 10.0));
+        let low = output.iter().position(|&point| point == (501.0, -10.0));
+        assert!(high.unwrap() < low.unwrap());
+        assert!(output.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn keeps_neighbours_of_the_range() {
+        let points: Vec<_> = (0..100).map(|i| (i as f64, i as f64)).collect();
+        let output = min_max_decimate(points.clone(), 10.5, 20.5, 5);
+        assert_eq!(output.first(), Some(&(10.0, 10.0)));
+        assert_eq!(output.last(), Some(&(21.0, 21.0)));
+        assert!(output[1..output.len() - 1]
+            .iter()
+            .all(|&(x, _)| (10.5..=20.5).contains(&x)));
+
+        // Only the last point before the range is left if the range is past the data
+        let output = min_max_decimate(points, 200.0, 300.0, 5);
+        assert_eq!(output, vec![(99.0, 99.0)]);
+    }
+}
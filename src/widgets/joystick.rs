@@ -0,0 +1,462 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+use std::os::unix::io::AsRawFd;
+
+/// Channels that can be controlled with the joystick together with the default axis
+const CHANNELS: [(&str, evdev::AbsoluteAxisType, bool); 4] = [
+    ("Throttle", evdev::AbsoluteAxisType::ABS_Y, true),
+    ("Roll", evdev::AbsoluteAxisType::ABS_RX, false),
+    ("Pitch", evdev::AbsoluteAxisType::ABS_RY, false),
+    ("Yaw", evdev::AbsoluteAxisType::ABS_X, false),
+];
+const THROTTLE: usize = 0;
+const ROLL: usize = 1;
+const PITCH: usize = 2;
+const YAW: usize = 3;
+
+/// Time between two updates of the mapped input in ms. Axis events in between are combined.
+const INPUT_PERIOD: u32 = 20;
+
+/// Longest time the reader thread waits for events in ms before it checks if it should stop
+const READ_TIMEOUT: i32 = 50;
+
+/// Actions that can be triggered by a button
+const ACTIONS: [&str; 3] = ["Arm", "Disarm", "Mode Switch"];
+
+pub struct Model {
+    relm: Relm<Widget>,
+    reciver: Option<relm::Channel<Message>>,
+    /// Tells the reader thread of the enabled device to close it
+    stop_reader: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    axis_values: std::collections::HashMap<u16, f64>,
+    /// An axis moved since the last input update
+    axis_changed: bool,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    RefreshDeviceList,
+    DeviceChanged,
+    Enable,
+    DeviceError,
+    Axis(u16, f64),
+    Button(u16, bool),
+    Tick,
+    Input(f64, f64, f64, f64),
+    Arm,
+    Disarm,
+    ModeSwitch,
+}
+
+/// Mapping of one joystick axis to a control channel
+struct ChannelMapping {
+    axis: gtk::ComboBoxText,
+    invert: gtk::CheckButton,
+}
+
+/// Reads an evdev device (`/dev/input/event*`) and maps the axes to roll, pitch, yaw and throttle.
+/// A virtual device created with uinput shows up in the device list like any real gamepad,
+/// see `examples/virtual_joystick.rs`.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    device_list: gtk::ComboBoxText,
+    btn_enable: gtk::ToggleButton,
+    channels: Vec<ChannelMapping>,
+    deadband: gtk::Adjustment,
+    buttons: Vec<gtk::ComboBoxText>,
+}
+
+impl Widget {
+    fn refresh_device_list(&self) {
+        self.device_list.remove_all();
+        for (path, device) in evdev::enumerate() {
+            // Only devices with axes can be used as joystick
+            if device.supported_absolute_axes().is_none() {
+                continue;
+            }
+            let path = path.to_string_lossy();
+            let name = device.name().unwrap_or("Unknown Device");
+            self.device_list
+                .append(Some(&path), &format!("{} ({})", name, path));
+        }
+        self.device_list.set_active(Some(0));
+    }
+
+    /// Fill the axis and button selection with the capabilities of the selected device
+    fn fill_mapping(&self) {
+        let device = self
+            .device_list
+            .get_active_id()
+            .and_then(|path| evdev::Device::open(path.as_str()).ok());
+
+        for (mapping, &(_, default_axis, default_invert)) in
+            self.channels.iter().zip(CHANNELS.iter())
+        {
+            mapping.axis.remove_all();
+            if let Some(axes) = device
+                .as_ref()
+                .and_then(|device| device.supported_absolute_axes())
+            {
+                for axis in axes.iter() {
+                    mapping
+                        .axis
+                        .append(Some(&axis.0.to_string()), &format!("{:?}", axis));
+                }
+            }
+            mapping
+                .axis
+                .set_active_id(Some(&default_axis.0.to_string()));
+            mapping.invert.set_active(default_invert);
+        }
+
+        for button in self.buttons.iter() {
+            button.remove_all();
+            button.append(Some("none"), "-");
+            if let Some(keys) = device.as_ref().and_then(|device| device.supported_keys()) {
+                for key in keys.iter() {
+                    button.append(Some(&key.code().to_string()), &format!("{:?}", key));
+                }
+            }
+            button.set_active_id(Some("none"));
+        }
+    }
+
+    /// Start a thread that reads the events of the selected device.
+    /// The axes start at their current position. The thread ends when it is stopped or the channel is droped.
+    fn enable(&mut self) {
+        let path = match self.device_list.get_active_id() {
+            Some(path) => path.to_string(),
+            None => {
+                self.model.relm.stream().emit(Message::DeviceError);
+                return;
+            }
+        };
+        let mut device = match evdev::Device::open(&path) {
+            Ok(device) => device,
+            Err(_) => {
+                self.model.relm.stream().emit(Message::DeviceError);
+                return;
+            }
+        };
+        let abs_state = device.get_abs_state().ok();
+
+        // Current position of the axes, so sticks that are not moved have their real value
+        let mut axis_values = std::collections::HashMap::new();
+        if let (Some(state), Some(axes)) = (abs_state, device.supported_absolute_axes()) {
+            for axis in axes.iter() {
+                let info = state[axis.0 as usize];
+                axis_values.insert(axis.0, normalize(info.value, info.minimum, info.maximum));
+            }
+        }
+
+        let stream = self.model.relm.stream().clone();
+        let (reciver, sender) = relm::Channel::<Message>::new(move |msg| stream.emit(msg));
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        self.model.stop_reader = stop.clone();
+
+        std::thread::spawn(move || {
+            let mut poll_fd = libc::pollfd {
+                fd: device.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            while !stop.load(std::sync::atomic::Ordering::SeqCst) {
+                // Only read when events are available, so the stop flag is checked regularly
+                let ready = unsafe { libc::poll(&mut poll_fd, 1, READ_TIMEOUT) };
+                if ready < 0 {
+                    if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    sender.send(Message::DeviceError).ok();
+                    return;
+                }
+                if ready == 0 {
+                    continue;
+                }
+                let events = match device.fetch_events() {
+                    Ok(events) => events,
+                    Err(_) => {
+                        sender.send(Message::DeviceError).ok();
+                        return;
+                    }
+                };
+                for event in events {
+                    let msg = match event.kind() {
+                        // Without the range of the axes the values can not be normalized
+                        evdev::InputEventKind::AbsAxis(axis) => match abs_state {
+                            Some(state) => {
+                                let info = state[axis.0 as usize];
+                                let value = normalize(event.value(), info.minimum, info.maximum);
+                                Message::Axis(axis.0, value)
+                            }
+                            None => continue,
+                        },
+                        evdev::InputEventKind::Key(key) => {
+                            Message::Button(key.code(), event.value() == 1)
+                        }
+                        _ => continue,
+                    };
+                    if sender.send(msg).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        self.model.reciver = Some(reciver);
+        self.model.axis_values = axis_values;
+        self.model.axis_changed = false;
+    }
+
+    /// Stop the reader thread, which closes the device
+    fn disable(&mut self) {
+        self.model
+            .stop_reader
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.model.reciver.take();
+    }
+
+    /// Value of a channel with inversion applied. The range is -1..1.
+    /// `None` if the mapped axis has not reported a value yet.
+    fn channel_value(&self, channel: usize) -> Option<f64> {
+        let mapping = &self.channels[channel];
+        let value = mapping
+            .axis
+            .get_active_id()
+            .and_then(|axis| axis.parse::<u16>().ok())
+            .and_then(|axis| self.model.axis_values.get(&axis).copied())?;
+        Some(if mapping.invert.get_active() {
+            -value
+        } else {
+            value
+        })
+    }
+}
+
+/// Normalize a raw axis value to -1..1
+fn normalize(value: i32, minimum: i32, maximum: i32) -> f64 {
+    let range = (maximum - minimum).max(1) as f64;
+    2.0 * (value - minimum) as f64 / range - 1.0
+}
+
+/// Map the throttle axis from -1..1 to 0..1
+fn throttle(value: f64) -> f64 {
+    (value + 1.0) / 2.0
+}
+
+/// Set values within the deadband around the center to zero and rescale the rest to the full range
+fn apply_deadband(value: f64, deadband: f64) -> f64 {
+    if value.abs() <= deadband {
+        0.0
+    } else {
+        value.signum() * (value.abs() - deadband) / (1.0 - deadband)
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), INPUT_PERIOD, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            reciver: None,
+            stop_reader: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            axis_values: std::collections::HashMap::new(),
+            axis_changed: false,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::RefreshDeviceList => self.refresh_device_list(),
+            Message::DeviceChanged => self.fill_mapping(),
+            Message::Enable => {
+                if self.btn_enable.get_active() {
+                    self.enable();
+                } else {
+                    self.disable();
+                }
+                self.device_list
+                    .set_sensitive(!self.btn_enable.get_active());
+            }
+            Message::DeviceError => {
+                self.disable();
+                self.btn_enable.set_active(false);
+            }
+            Message::Axis(axis, value) => {
+                self.model.axis_values.insert(axis, value);
+                self.model.axis_changed = true;
+            }
+            Message::Tick => {
+                if !self.model.axis_changed {
+                    return;
+                }
+                self.model.axis_changed = false;
+                // Without a real throttle value a stick at rest would give half throttle
+                let throttle = match self.channel_value(THROTTLE) {
+                    Some(value) => throttle(value),
+                    None => return,
+                };
+                // The deadband is only used for the centered channels
+                let deadband = self.deadband.get_value();
+                let centered =
+                    |channel| apply_deadband(self.channel_value(channel).unwrap_or(0.0), deadband);
+                self.model.relm.stream().emit(Message::Input(
+                    centered(ROLL),
+                    centered(PITCH),
+                    centered(YAW),
+                    throttle,
+                ));
+            }
+            Message::Button(code, true) => {
+                let code = code.to_string();
+                for (index, button) in self.buttons.iter().enumerate() {
+                    if button
+                        .get_active_id()
+                        .map_or(false, |id| id.as_str() == code)
+                    {
+                        let msg = match index {
+                            0 => Message::Arm,
+                            1 => Message::Disarm,
+                            _ => Message::ModeSwitch,
+                        };
+                        self.model.relm.stream().emit(msg);
+                    }
+                }
+            }
+            Message::Button(_, false) => (),
+            Message::Input(..) => (),
+            Message::Arm => (),
+            Message::Disarm => (),
+            Message::ModeSwitch => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Joystick"));
+        let grid = gtk::Grid::new();
+        grid.set_column_spacing(5);
+        grid.set_row_spacing(5);
+        root.add(&grid);
+
+        // Device selection
+        let device_list = gtk::ComboBoxText::new();
+        device_list.set_hexpand(true);
+        grid.attach(&device_list, 0, 0, 2, 1);
+        let btn_refresh = gtk::Button::new();
+        btn_refresh.set_label("Refresh");
+        grid.attach(&btn_refresh, 2, 0, 1, 1);
+        let btn_enable = gtk::ToggleButton::new();
+        btn_enable.set_label("Enable");
+        grid.attach(&btn_enable, 3, 0, 1, 1);
+
+        // Axis mapping
+        let mut channels = Vec::new();
+        for (row, &(label, _, _)) in CHANNELS.iter().enumerate() {
+            let row = row as i32 + 1;
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row, 1, 1);
+            let axis = gtk::ComboBoxText::new();
+            grid.attach(&axis, 1, row, 1, 1);
+            let invert = gtk::CheckButton::with_label("Invert");
+            grid.attach(&invert, 2, row, 2, 1);
+            channels.push(ChannelMapping { axis, invert });
+        }
+
+        let label_deadband = gtk::Label::new(Some("Deadband"));
+        label_deadband.set_xalign(0.0);
+        grid.attach(&label_deadband, 0, 5, 1, 1);
+        let deadband = gtk::Adjustment::new(0.05, 0.0, 0.5, 0.01, 0.1, 0.0);
+        grid.attach(&gtk::SpinButton::new(Some(&deadband), 0.01, 2), 1, 5, 1, 1);
+
+        // Button bindings
+        let mut buttons = Vec::new();
+        for (row, &label) in ACTIONS.iter().enumerate() {
+            let row = row as i32 + 6;
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row, 1, 1);
+            let button = gtk::ComboBoxText::new();
+            grid.attach(&button, 1, row, 1, 1);
+            buttons.push(button);
+        }
+
+        // Connect events
+        connect!(
+            relm,
+            btn_refresh,
+            connect_clicked(_),
+            Message::RefreshDeviceList
+        );
+        connect!(relm, btn_enable, connect_toggled(_), Message::Enable);
+        connect!(
+            relm,
+            device_list,
+            connect_changed(_),
+            Message::DeviceChanged
+        );
+
+        // Trigger filling of the devicelist
+        relm.stream().emit(Message::RefreshDeviceList);
+
+        Self {
+            model,
+            root,
+            device_list,
+            btn_enable,
+            channels,
+            deadband,
+            buttons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_axis_range() {
+        assert_eq!(normalize(-32768, -32768, 32767), -1.0);
+        assert_eq!(normalize(32767, -32768, 32767), 1.0);
+        assert!(normalize(0, -32768, 32767).abs() < 1e-4);
+        assert_eq!(normalize(128, 0, 255), 2.0 * 128.0 / 255.0 - 1.0);
+        // A broken range must not divide by zero
+        assert!(normalize(5, 5, 5).is_finite());
+    }
+
+    #[test]
+    fn throttle_at_rest_is_zero() {
+        // The default throttle axis is inverted and rests at its maximum
+        assert_eq!(throttle(-normalize(32767, -32768, 32767)), 0.0);
+        assert_eq!(throttle(-normalize(-32768, -32768, 32767)), 1.0);
+    }
+
+    #[test]
+    fn deadband_rescales() {
+        assert_eq!(apply_deadband(0.04, 0.05), 0.0);
+        assert_eq!(apply_deadband(-0.05, 0.05), 0.0);
+        assert_eq!(apply_deadband(1.0, 0.05), 1.0);
+        assert_eq!(apply_deadband(-1.0, 0.05), -1.0);
+        assert!((apply_deadband(0.525, 0.05) - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,209 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+/// Time between two updates of the inputs in ms
+const TICK: u32 = 50;
+
+const ROLL: usize = 0;
+const PITCH: usize = 1;
+const YAW: usize = 2;
+const THROTTLE: usize = 3;
+
+/// Channel and direction that is moved by a key
+fn key_axis(key: &gdk::keys::Key) -> Option<(usize, f64)> {
+    use gdk::keys::constants as keys;
+    match *key {
+        keys::w | keys::W => Some((PITCH, 1.0)),
+        keys::s | keys::S => Some((PITCH, -1.0)),
+        keys::a | keys::A => Some((ROLL, -1.0)),
+        keys::d | keys::D => Some((ROLL, 1.0)),
+        keys::Left => Some((YAW, -1.0)),
+        keys::Right => Some((YAW, 1.0)),
+        keys::Up => Some((THROTTLE, 1.0)),
+        keys::Down => Some((THROTTLE, -1.0)),
+        _ => None,
+    }
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    window: gtk::Window,
+    pressed: std::collections::HashSet<gdk::keys::Key>,
+    values: [f64; 4],
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Enable,
+    KeyPress(gdk::keys::Key),
+    KeyRelease(gdk::keys::Key),
+    FocusOut,
+    Tick,
+    Input(f64, f64, f64, f64),
+}
+
+/// Control roll, pitch, yaw and throttle with the keyboard of the main window.
+/// WASD moves pitch and roll, the arrow keys throttle and yaw.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    btn_enable: gtk::ToggleButton,
+    step: gtk::Adjustment,
+    decay: gtk::CheckButton,
+    decay_rate: gtk::Adjustment,
+}
+
+impl Widget {
+    /// Move the inputs according to the pressed keys. Returns true if a value changed.
+    fn tick(&mut self) -> bool {
+        let dt = TICK as f64 / 1000.0;
+        let step = self.step.get_value() * dt;
+        let decay = if self.decay.get_active() {
+            self.decay_rate.get_value() * dt
+        } else {
+            0.0
+        };
+
+        let mut direction = [0.0; 4];
+        for (axis, dir) in self.model.pressed.iter().filter_map(key_axis) {
+            direction[axis] += dir;
+        }
+
+        let old_values = self.model.values;
+        for (axis, value) in self.model.values.iter_mut().enumerate() {
+            if axis == THROTTLE {
+                // The throttle stays where it was left
+                *value = (*value + direction[axis] * step).min(1.0).max(0.0);
+            } else if direction[axis] != 0.0 {
+                *value = (*value + direction[axis] * step).min(1.0).max(-1.0);
+            } else {
+                *value = value.signum() * (value.abs() - decay).max(0.0);
+            }
+        }
+        old_values != self.model.values
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = gtk::Window;
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, window: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            window,
+            pressed: std::collections::HashSet::new(),
+            values: [0.0; 4],
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Enable => self.model.pressed.clear(),
+            // Shift may change between press and release, so only the lower case keys are stored
+            Message::KeyPress(key) => {
+                self.model.pressed.insert(key.to_lower());
+            }
+            Message::KeyRelease(key) => {
+                self.model.pressed.remove(&key.to_lower());
+            }
+            // The release of keys that are held while the window loses the focus is never recived
+            Message::FocusOut => self.model.pressed.clear(),
+            Message::Tick => {
+                if self.btn_enable.get_active() && self.tick() {
+                    let values = self.model.values;
+                    self.model.relm.stream().emit(Message::Input(
+                        values[ROLL],
+                        values[PITCH],
+                        values[YAW],
+                        values[THROTTLE],
+                    ));
+                }
+            }
+            Message::Input(..) => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Keyboard"));
+        let grid = gtk::Grid::new();
+        grid.set_column_spacing(5);
+        root.add(&grid);
+
+        let btn_enable = gtk::ToggleButton::new();
+        btn_enable.set_label("Keyboard Control");
+        grid.attach(&btn_enable, 0, 0, 2, 1);
+
+        let label_step = gtk::Label::new(Some("Step [1/s]"));
+        label_step.set_xalign(0.0);
+        grid.attach(&label_step, 0, 1, 1, 1);
+        let step = gtk::Adjustment::new(1.0, 0.1, 10.0, 0.1, 1.0, 0.0);
+        grid.attach(&gtk::SpinButton::new(Some(&step), 0.1, 1), 1, 1, 1, 1);
+
+        let decay = gtk::CheckButton::with_label("Decay to Center [1/s]");
+        decay.set_active(true);
+        grid.attach(&decay, 0, 2, 1, 1);
+        let decay_rate = gtk::Adjustment::new(2.0, 0.1, 10.0, 0.1, 1.0, 0.0);
+        grid.attach(&gtk::SpinButton::new(Some(&decay_rate), 0.1, 1), 1, 2, 1, 1);
+
+        connect!(relm, btn_enable, connect_toggled(_), Message::Enable);
+
+        // Keys are only taken from the window while the keyboard control is active.
+        // In this case they are not passed on to the focused widget.
+        {
+            let stream = relm.stream().clone();
+            let btn_enable = btn_enable.clone();
+            model.window.connect_key_press_event(move |_, key| {
+                if btn_enable.get_active() && key_axis(&key.get_keyval()).is_some() {
+                    stream.emit(Message::KeyPress(key.get_keyval()));
+                    Inhibit(true)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+        {
+            let stream = relm.stream().clone();
+            let btn_enable = btn_enable.clone();
+            model.window.connect_key_release_event(move |_, key| {
+                if btn_enable.get_active() && key_axis(&key.get_keyval()).is_some() {
+                    stream.emit(Message::KeyRelease(key.get_keyval()));
+                    Inhibit(true)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+
+        connect!(
+            relm,
+            model.window,
+            connect_focus_out_event(_, _),
+            return (Some(Message::FocusOut), Inhibit(false))
+        );
+
+        Self {
+            model,
+            root,
+            btn_enable,
+            step,
+            decay,
+            decay_rate,
+        }
+    }
+}
@@ -0,0 +1,357 @@
+// Things from relm
+use relm::Relm;
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+/// Time between two updates of the limited setpoint in ms
+const TICK: u32 = 20;
+
+/// Label, lower and upper bound of the range, default min and max and default rate of a channel
+type ChannelDefaults = (&'static str, f64, f64, f64, f64, f64);
+
+const THROTTLE_DEFAULTS: ChannelDefaults = ("Throttle [%]", 0.0, 100.0, 0.0, 100.0, 50.0);
+const MOTOR_DEFAULTS: [ChannelDefaults; 4] = [
+    ("Front Left [%]", 0.0, 100.0, 0.0, 100.0, 50.0),
+    ("Front Right [%]", 0.0, 100.0, 0.0, 100.0, 50.0),
+    ("Rear Left [%]", 0.0, 100.0, 0.0, 100.0, 50.0),
+    ("Rear Right [%]", 0.0, 100.0, 0.0, 100.0, 50.0),
+];
+
+/// Limits of each mode in the order of the setpoint values. The ranges are the full range of each value in its unit.
+const MODES: [(&str, [ChannelDefaults; 4]); 4] = [
+    (
+        "PRYT",
+        [
+            ("Pitch [%]", -100.0, 100.0, -100.0, 100.0, 200.0),
+            ("Roll [%]", -100.0, 100.0, -100.0, 100.0, 200.0),
+            ("Yaw [%]", -100.0, 100.0, -100.0, 100.0, 200.0),
+            THROTTLE_DEFAULTS,
+        ],
+    ),
+    (
+        "Stabilize",
+        [
+            ("Pitch [°]", -90.0, 90.0, -30.0, 30.0, 90.0),
+            ("Roll [°]", -90.0, 90.0, -30.0, 30.0, 90.0),
+            ("Yaw [°/s]", -360.0, 360.0, -90.0, 90.0, 180.0),
+            THROTTLE_DEFAULTS,
+        ],
+    ),
+    (
+        "Angle",
+        [
+            ("Pitch [°]", -90.0, 90.0, -30.0, 30.0, 90.0),
+            ("Roll [°]", -90.0, 90.0, -30.0, 30.0, 90.0),
+            ("Yaw [°]", -180.0, 180.0, -180.0, 180.0, 180.0),
+            THROTTLE_DEFAULTS,
+        ],
+    ),
+    ("Direct", MOTOR_DEFAULTS),
+];
+const PRYT: usize = 0;
+const STABILIZE: usize = 1;
+const ANGLE: usize = 2;
+const DIRECT: usize = 3;
+const THROTTLE: usize = 3;
+
+/// Envelope and maximum rate of change of one channel
+#[derive(Clone, Copy)]
+struct ChannelLimit {
+    min: f32,
+    max: f32,
+    rate: f32,
+}
+
+/// Values of a setpoint as channels. The sequence test has no values that can be limited.
+fn channels(setpoint: &copter_com::SetValues) -> Option<[f32; 4]> {
+    match *setpoint {
+        copter_com::SetValues::SequenceTest => None,
+        copter_com::SetValues::DirectControl((a, b, c, d))
+        | copter_com::SetValues::PRYTControl((a, b, c, d))
+        | copter_com::SetValues::Stabilize((a, b, c, d))
+        | copter_com::SetValues::AngleControl((a, b, c, d)) => Some([a, b, c, d]),
+    }
+}
+
+/// Setpoint of the same mode with new values
+fn with_channels(setpoint: &copter_com::SetValues, values: [f32; 4]) -> copter_com::SetValues {
+    let values = (values[0], values[1], values[2], values[3]);
+    match *setpoint {
+        copter_com::SetValues::SequenceTest => copter_com::SetValues::SequenceTest,
+        copter_com::SetValues::DirectControl(_) => copter_com::SetValues::DirectControl(values),
+        copter_com::SetValues::PRYTControl(_) => copter_com::SetValues::PRYTControl(values),
+        copter_com::SetValues::Stabilize(_) => copter_com::SetValues::Stabilize(values),
+        copter_com::SetValues::AngleControl(_) => copter_com::SetValues::AngleControl(values),
+    }
+}
+
+/// Index of the limits in `MODES` that belong to a setpoint
+fn mode_index(setpoint: &copter_com::SetValues) -> Option<usize> {
+    match setpoint {
+        copter_com::SetValues::SequenceTest => None,
+        copter_com::SetValues::PRYTControl(_) => Some(PRYT),
+        copter_com::SetValues::Stabilize(_) => Some(STABILIZE),
+        copter_com::SetValues::AngleControl(_) => Some(ANGLE),
+        copter_com::SetValues::DirectControl(_) => Some(DIRECT),
+    }
+}
+
+/// Throttle of a setpoint. For the direct control this is the highest motor output.
+fn throttle(setpoint: &copter_com::SetValues) -> f32 {
+    match *setpoint {
+        copter_com::SetValues::DirectControl((a, b, c, d)) => a.max(b).max(c).max(d),
+        _ => channels(setpoint).map_or(0.0, |values| values[THROTTLE]),
+    }
+}
+
+/// Move each channel towards the target within its envelope and with its maximum rate.
+/// Returns the new values and which channels were limited.
+fn limit(
+    target: [f32; 4],
+    output: [f32; 4],
+    limits: [ChannelLimit; 4],
+    dt: f32,
+) -> ([f32; 4], [bool; 4]) {
+    let mut values = output;
+    let mut active = [false; 4];
+    for (i, value) in values.iter_mut().enumerate() {
+        let clamped = target[i].min(limits[i].max).max(limits[i].min);
+        let max_step = limits[i].rate * dt;
+        *value += (clamped - *value).min(max_step).max(-max_step);
+        active[i] = (*value - target[i]).abs() > f32::EPSILON;
+    }
+    (values, active)
+}
+
+/// Check if two setpoints have the same mode and values
+fn same_setpoint(a: &copter_com::SetValues, b: &copter_com::SetValues) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b) && channels(a) == channels(b)
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    target: Option<copter_com::SetValues>,
+    output: Option<copter_com::SetValues>,
+    /// The rate of change is limited. Without it only the envelope is applied.
+    slew: bool,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Input(Option<copter_com::SetValues>),
+    /// Enable or disable the rate limit. Scripted tests disable it, because the timing of their steps is part of the test.
+    SetSlewLimit(bool),
+    Tick,
+    Output(Option<copter_com::SetValues>),
+}
+
+/// Inputs for the limits of one channel
+struct ChannelInput {
+    min: gtk::Adjustment,
+    max: gtk::Adjustment,
+    rate: gtk::Adjustment,
+    indicator: gtk::Label,
+}
+
+/// Limits the setpoints between the control inputs and the connection.
+/// Each mode has its own limits, so the units of the values never mix.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    modes: Vec<Vec<ChannelInput>>,
+}
+
+impl Widget {
+    /// Limits of the channels of a mode
+    fn limits(&self, mode: usize) -> [ChannelLimit; 4] {
+        let limit = |channel: &ChannelInput| ChannelLimit {
+            min: channel.min.get_value() as f32,
+            max: channel.max.get_value() as f32,
+            rate: channel.rate.get_value() as f32,
+        };
+        let channels = &self.modes[mode];
+        [
+            limit(&channels[0]),
+            limit(&channels[1]),
+            limit(&channels[2]),
+            limit(&channels[3]),
+        ]
+    }
+
+    /// Limited setpoint for the next tick
+    fn step(&self, target: &copter_com::SetValues) -> (copter_com::SetValues, [bool; 4]) {
+        let (target_values, mode) = match (channels(target), mode_index(target)) {
+            (Some(values), Some(mode)) => (values, mode),
+            _ => return (*target, [false; 4]),
+        };
+        let mut limits = self.limits(mode);
+        if !self.model.slew {
+            for limit in limits.iter_mut() {
+                limit.rate = f32::INFINITY;
+            }
+        }
+
+        // Start from the last output. If the mode changed only the throttle is kept.
+        let output_values = match self.model.output.as_ref() {
+            Some(output) if std::mem::discriminant(output) == std::mem::discriminant(target) => {
+                channels(output).unwrap_or(target_values)
+            }
+            output => {
+                let last_throttle = output.map_or(0.0, throttle);
+                let mut values = [0.0; 4];
+                for (i, value) in values.iter_mut().enumerate() {
+                    *value = target_values[i].min(limits[i].max).max(limits[i].min);
+                }
+                match target {
+                    copter_com::SetValues::DirectControl(_) => [last_throttle; 4],
+                    _ => {
+                        values[THROTTLE] = last_throttle;
+                        values
+                    }
+                }
+            }
+        };
+
+        let (values, active) = limit(target_values, output_values, limits, TICK as f32 / 1000.0);
+        (with_channels(target, values), active)
+    }
+
+    /// Show which channels of a mode are limited. The indicators of all other modes are cleared.
+    fn show_active(&self, mode: Option<usize>, active: [bool; 4]) {
+        for (index, channels) in self.modes.iter().enumerate() {
+            for (i, channel) in channels.iter().enumerate() {
+                if mode == Some(index) && active[i] {
+                    channel
+                        .indicator
+                        .set_markup("<span foreground=\"red\">LIMIT</span>");
+                } else {
+                    channel.indicator.set_text("");
+                }
+            }
+        }
+    }
+
+    /// Move the output towards the target and send it if it changed
+    fn update_output(&mut self) {
+        if let Some(target) = self.model.target {
+            let (output, active) = self.step(&target);
+            self.show_active(mode_index(&output), active);
+            let changed = self
+                .model
+                .output
+                .map_or(true, |last| !same_setpoint(&last, &output));
+            if changed {
+                self.model.output = Some(output);
+                self.model.relm.stream().emit(Message::Output(Some(output)));
+            }
+        }
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            target: None,
+            output: None,
+            slew: true,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Input(None) => {
+                // Stop the stream immediately
+                self.model.target = None;
+                self.model.output = None;
+                self.show_active(None, [false; 4]);
+                self.model.relm.stream().emit(Message::Output(None));
+            }
+            Message::Input(Some(setpoint)) => {
+                self.model.target = Some(setpoint);
+                // Without the rate limit there is nothing to wait for
+                if !self.model.slew {
+                    self.update_output();
+                }
+            }
+            Message::SetSlewLimit(slew) => self.model.slew = slew,
+            Message::Tick => self.update_output(),
+            Message::Output(_) => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(_relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Setpoint Limits"));
+        let notebook = gtk::Notebook::new();
+        root.add(&notebook);
+
+        let mut modes = Vec::new();
+        for &(mode, defaults) in MODES.iter() {
+            let grid = gtk::Grid::new();
+            grid.set_column_spacing(5);
+            notebook.append_page(&grid, Some(&gtk::Label::new(Some(mode))));
+
+            for (column, &label) in ["Min", "Max", "Rate [unit/s]"].iter().enumerate() {
+                grid.attach(&gtk::Label::new(Some(label)), column as i32 + 1, 0, 1, 1);
+            }
+
+            let mut channels = Vec::new();
+            for (row, &(label, lower, upper, min, max, rate)) in defaults.iter().enumerate() {
+                let row = row as i32 + 1;
+                let label = gtk::Label::new(Some(label));
+                label.set_xalign(0.0);
+                grid.attach(&label, 0, row, 1, 1);
+
+                let channel = ChannelInput {
+                    min: gtk::Adjustment::new(min, lower, upper, 1.0, 10.0, 0.0),
+                    max: gtk::Adjustment::new(max, lower, upper, 1.0, 10.0, 0.0),
+                    rate: gtk::Adjustment::new(rate, 1.0, 1000.0, 1.0, 10.0, 0.0),
+                    indicator: gtk::Label::new(None),
+                };
+                grid.attach(
+                    &gtk::SpinButton::new(Some(&channel.min), 1.0, 0),
+                    1,
+                    row,
+                    1,
+                    1,
+                );
+                grid.attach(
+                    &gtk::SpinButton::new(Some(&channel.max), 1.0, 0),
+                    2,
+                    row,
+                    1,
+                    1,
+                );
+                grid.attach(
+                    &gtk::SpinButton::new(Some(&channel.rate), 1.0, 0),
+                    3,
+                    row,
+                    1,
+                    1,
+                );
+                channel.indicator.set_width_chars(5);
+                grid.attach(&channel.indicator, 4, row, 1, 1);
+                channels.push(channel);
+            }
+            modes.push(channels);
+        }
+
+        Self { model, root, modes }
+    }
+}
@@ -1,3 +1,13 @@
+pub mod autotune;
+pub mod bode;
 pub mod connection;
 pub mod control;
+pub mod experiment;
+pub mod failsafe;
 pub mod graph;
+pub mod joystick;
+pub mod keyboard;
+pub mod limiter;
+pub mod sequence;
+pub mod sweep;
+pub mod trim;
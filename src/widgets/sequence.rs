@@ -0,0 +1,509 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+/// Time between two setpoints of a running sequence in ms
+const TICK: u32 = 20;
+
+/// Columns of the step table
+const COLUMNS: [&str; 5] = [
+    "Front Left",
+    "Front Right",
+    "Rear Left",
+    "Rear Right",
+    "Duration [s]",
+];
+const COLUMN_DURATION: u32 = 4;
+const COLUMN_RAMP: u32 = 5;
+
+/// Range of the motor outputs in %
+const MOTOR_MIN: f64 = 0.0;
+const MOTOR_MAX: f64 = 100.0;
+
+/// First line of a sequence file
+const FILE_HEADER: &str = "# front_left,front_right,rear_left,rear_right,duration_s,ramp";
+
+/// One step of a motor test sequence
+#[derive(Clone, Copy)]
+struct Step {
+    motors: [f32; 4],
+    duration: f64,
+    /// Ramp from the outputs of the previous step instead of jumping
+    ramp: bool,
+}
+
+/// Motor outputs of the sequence at a point in time. `None` if the sequence is finished.
+fn sequence_output(steps: &[Step], elapsed: f64) -> Option<[f32; 4]> {
+    let mut start = 0.0;
+    let mut previous = [0.0; 4];
+    for step in steps {
+        if elapsed < start + step.duration {
+            if !step.ramp {
+                return Some(step.motors);
+            }
+            let fraction = ((elapsed - start) / step.duration) as f32;
+            let mut values = previous;
+            for (value, target) in values.iter_mut().zip(step.motors.iter()) {
+                *value += (target - *value) * fraction;
+            }
+            return Some(values);
+        }
+        start += step.duration;
+        previous = step.motors;
+    }
+    None
+}
+
+/// Limit a motor output to its range
+fn clamp_motor(value: f64) -> f64 {
+    value.min(MOTOR_MAX).max(MOTOR_MIN)
+}
+
+/// Parse the steps of a sequence file. Motor outputs are clamped to their range like in the table.
+fn parse_sequence(content: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() != 6 {
+            return Err(format!("line {}: expected 6 values", number + 1));
+        }
+        let mut motors = [0.0; 4];
+        for (motor, field) in motors.iter_mut().zip(fields.iter()) {
+            let value = field
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| format!("line {}: invalid motor output", number + 1))?;
+            *motor = clamp_motor(value) as f32;
+        }
+        let duration = fields[4]
+            .parse::<f64>()
+            .ok()
+            .filter(|duration| duration.is_finite() && *duration > 0.0)
+            .ok_or_else(|| format!("line {}: duration must be above zero", number + 1))?;
+        let ramp = fields[5]
+            .parse()
+            .map_err(|_| format!("line {}: invalid ramp", number + 1))?;
+        steps.push(Step {
+            motors,
+            duration,
+            ramp,
+        });
+    }
+    Ok(steps)
+}
+
+/// Write the steps in the format of a sequence file
+fn format_sequence(steps: &[Step]) -> String {
+    let mut content = String::from(FILE_HEADER);
+    content.push('\n');
+    for step in steps {
+        content.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            step.motors[0],
+            step.motors[1],
+            step.motors[2],
+            step.motors[3],
+            step.duration,
+            step.ramp
+        ));
+    }
+    content
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    running: Option<(std::time::Instant, Vec<Step>)>,
+    /// Open file selection of a load or save
+    file_dialog: Option<(gtk::FileChooserDialog, gtk::FileChooserAction)>,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    AddStep,
+    RemoveStep,
+    Edited(u32, gtk::TreePath, String),
+    RampToggled(gtk::TreePath),
+    Load,
+    Save,
+    /// Answer of the file selection, `true` if a file was chosen
+    FileResponse(bool),
+    Run,
+    /// Abort by the operator. The motors are cut at once, because scripted setpoints skip the rate limit.
+    Stop,
+    /// Abort because the control left the scripted mode. No setpoint is sent,
+    /// the new mode or the stopped stream already decides the motor outputs.
+    Abort,
+    Tick,
+    SetPoint(copter_com::SetValues),
+}
+
+/// Editor for motor test sequences that are run by streaming direct control setpoints
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    store: gtk::ListStore,
+    tree_view: gtk::TreeView,
+    progress: gtk::ProgressBar,
+    editor: gtk::Box,
+}
+
+impl Widget {
+    fn steps(&self) -> Vec<Step> {
+        let mut steps = Vec::new();
+        if let Some(iter) = self.store.get_iter_first() {
+            loop {
+                let value = |column: u32| {
+                    self.store
+                        .get_value(&iter, column as i32)
+                        .get_some::<f64>()
+                        .unwrap_or(0.0)
+                };
+                steps.push(Step {
+                    motors: [
+                        value(0) as f32,
+                        value(1) as f32,
+                        value(2) as f32,
+                        value(3) as f32,
+                    ],
+                    duration: value(COLUMN_DURATION),
+                    ramp: self
+                        .store
+                        .get_value(&iter, COLUMN_RAMP as i32)
+                        .get_some::<bool>()
+                        .unwrap_or(false),
+                });
+                if !self.store.iter_next(&iter) {
+                    break;
+                }
+            }
+        }
+        steps
+    }
+
+    fn add_step(&self, step: &Step) {
+        self.store.insert_with_values(
+            None,
+            &[0, 1, 2, 3, COLUMN_DURATION, COLUMN_RAMP],
+            &[
+                &(step.motors[0] as f64),
+                &(step.motors[1] as f64),
+                &(step.motors[2] as f64),
+                &(step.motors[3] as f64),
+                &step.duration,
+                &step.ramp,
+            ],
+        );
+    }
+
+    fn edited(&self, column: u32, path: &gtk::TreePath, text: &str) {
+        let value = match text.trim().parse::<f64>() {
+            Ok(value) if !value.is_finite() => return,
+            // Like in a sequence file a step needs a duration
+            Ok(value) if column == COLUMN_DURATION && value <= 0.0 => return,
+            Ok(value) if column == COLUMN_DURATION => value,
+            Ok(value) => clamp_motor(value),
+            Err(_) => return,
+        };
+        if let Some(iter) = self.store.get_iter(path) {
+            self.store.set_value(&iter, column, &value.to_value());
+        }
+    }
+
+    fn ramp_toggled(&self, path: &gtk::TreePath) {
+        if let Some(iter) = self.store.get_iter(path) {
+            let ramp = self
+                .store
+                .get_value(&iter, COLUMN_RAMP as i32)
+                .get_some::<bool>()
+                .unwrap_or(false);
+            self.store
+                .set_value(&iter, COLUMN_RAMP, &(!ramp).to_value());
+        }
+    }
+
+    /// Ask the operator for a file of the sequence. The answer is recived as `FileResponse`.
+    fn choose_file(&mut self, action: gtk::FileChooserAction) {
+        if self.model.file_dialog.is_some() {
+            return;
+        }
+        let window = self
+            .root
+            .get_toplevel()
+            .and_then(|widget| widget.downcast::<gtk::Window>().ok());
+        let accept = match action {
+            gtk::FileChooserAction::Save => "Save",
+            _ => "Open",
+        };
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Motor Test Sequence"),
+            window.as_ref(),
+            action,
+            &[
+                ("Cancel", gtk::ResponseType::Cancel),
+                (accept, gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        connect!(
+            self.model.relm,
+            dialog,
+            connect_response(_, response),
+            Message::FileResponse(response == gtk::ResponseType::Accept)
+        );
+        dialog.show();
+        self.model.file_dialog = Some((dialog, action));
+    }
+
+    /// Load or save the chosen file
+    fn file_response(&mut self, accepted: bool) {
+        let (dialog, action) = match self.model.file_dialog.take() {
+            Some(file_dialog) => file_dialog,
+            None => return,
+        };
+        let file = if accepted {
+            dialog.get_filename()
+        } else {
+            None
+        };
+        dialog.close();
+        if let Some(file) = file {
+            match action {
+                gtk::FileChooserAction::Save => self.save(&file),
+                _ => self.load(&file),
+            }
+        }
+    }
+
+    fn load(&self, file: &std::path::Path) {
+        match std::fs::read_to_string(&file)
+            .map_err(|err| err.to_string())
+            .and_then(|content| parse_sequence(&content))
+        {
+            Ok(steps) => {
+                self.store.clear();
+                for step in steps.iter() {
+                    self.add_step(step);
+                }
+                self.progress.set_text(Some("Loaded"));
+            }
+            Err(err) => self
+                .progress
+                .set_text(Some(&format!("Load failed: {}", err))),
+        }
+        self.progress.set_fraction(0.0);
+    }
+
+    fn save(&self, file: &std::path::Path) {
+        match std::fs::write(file, format_sequence(&self.steps())) {
+            Ok(()) => self.progress.set_text(Some("Saved")),
+            Err(err) => self
+                .progress
+                .set_text(Some(&format!("Save failed: {}", err))),
+        }
+    }
+
+    fn set_running(&mut self, running: Option<(std::time::Instant, Vec<Step>)>) {
+        self.editor.set_sensitive(running.is_none());
+        self.model.running = running;
+    }
+
+    /// End a running sequence. With `cut_motors` the motors are set to zero.
+    fn end(&mut self, state: &str, cut_motors: bool) {
+        if self.model.running.is_none() {
+            return;
+        }
+        self.set_running(None);
+        self.progress.set_text(Some(state));
+        if cut_motors {
+            self.model
+                .relm
+                .stream()
+                .emit(Message::SetPoint(copter_com::SetValues::DirectControl((
+                    0.0, 0.0, 0.0, 0.0,
+                ))));
+        }
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            running: None,
+            file_dialog: None,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::AddStep => self.add_step(&Step {
+                motors: [0.0; 4],
+                duration: 1.0,
+                ramp: false,
+            }),
+            Message::RemoveStep => {
+                if let Some((_, iter)) = self.tree_view.get_selection().get_selected() {
+                    self.store.remove(&iter);
+                }
+            }
+            Message::Edited(column, path, text) => self.edited(column, &path, &text),
+            Message::RampToggled(path) => self.ramp_toggled(&path),
+            Message::Load => self.choose_file(gtk::FileChooserAction::Open),
+            Message::Save => self.choose_file(gtk::FileChooserAction::Save),
+            Message::FileResponse(accepted) => self.file_response(accepted),
+            Message::Run => {
+                let steps = self.steps();
+                if !steps.is_empty() {
+                    self.set_running(Some((std::time::Instant::now(), steps)));
+                    self.progress.set_text(Some("Running"));
+                }
+            }
+            Message::Stop => self.end("Aborted", true),
+            Message::Abort => self.end("Aborted", false),
+            Message::Tick => {
+                let output = match &self.model.running {
+                    Some((start, steps)) => {
+                        let elapsed = start.elapsed().as_secs_f64();
+                        let total: f64 = steps.iter().map(|step| step.duration).sum();
+                        self.progress
+                            .set_fraction((elapsed / total.max(std::f64::EPSILON)).min(1.0));
+                        sequence_output(steps, elapsed)
+                    }
+                    None => return,
+                };
+                let motors = match output {
+                    Some(motors) => motors,
+                    // Leave the motors at zero after the last step
+                    None => return self.end("Finished", true),
+                };
+                self.model.relm.stream().emit(Message::SetPoint(
+                    copter_com::SetValues::DirectControl((
+                        motors[0], motors[1], motors[2], motors[3],
+                    )),
+                ));
+            }
+            Message::SetPoint(_) => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Motor Test Sequence"));
+        let root_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        root.add(&root_box);
+
+        // Table of the steps
+        let store = gtk::ListStore::new(&[
+            glib::Type::F64,
+            glib::Type::F64,
+            glib::Type::F64,
+            glib::Type::F64,
+            glib::Type::F64,
+            glib::Type::Bool,
+        ]);
+        let tree_view = gtk::TreeView::with_model(&store);
+        for (column, &title) in COLUMNS.iter().enumerate() {
+            let column = column as u32;
+            let renderer = gtk::CellRendererText::new();
+            renderer.set_property_editable(true);
+            let tree_column = gtk::TreeViewColumn::new();
+            tree_column.set_title(title);
+            tree_column.pack_start(&renderer, true);
+            tree_column.add_attribute(&renderer, "text", column as i32);
+            tree_view.append_column(&tree_column);
+            connect!(
+                relm,
+                renderer,
+                connect_edited(_, path, text),
+                Message::Edited(column, path, text.to_string())
+            );
+        }
+        let renderer = gtk::CellRendererToggle::new();
+        let tree_column = gtk::TreeViewColumn::new();
+        tree_column.set_title("Ramp");
+        tree_column.pack_start(&renderer, true);
+        tree_column.add_attribute(&renderer, "active", COLUMN_RAMP as i32);
+        tree_view.append_column(&tree_column);
+        connect!(
+            relm,
+            renderer,
+            connect_toggled(_, path),
+            Message::RampToggled(path)
+        );
+
+        let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled.set_size_request(-1, 120);
+        scrolled.add(&tree_view);
+
+        // Buttons to edit the sequence. They are locked while the sequence runs.
+        let editor = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        root_box.add(&editor);
+        editor.pack_start(&scrolled, true, true, 0);
+        let box_edit = gtk::ButtonBox::new(gtk::Orientation::Horizontal);
+        editor.add(&box_edit);
+        let btn_add = gtk::Button::new();
+        btn_add.set_label("Add Step");
+        box_edit.add(&btn_add);
+        let btn_remove = gtk::Button::new();
+        btn_remove.set_label("Remove Step");
+        box_edit.add(&btn_remove);
+        let btn_load = gtk::Button::new();
+        btn_load.set_label("Load");
+        box_edit.add(&btn_load);
+        let btn_save = gtk::Button::new();
+        btn_save.set_label("Save");
+        box_edit.add(&btn_save);
+        let btn_run = gtk::Button::new();
+        btn_run.set_label("Run");
+        box_edit.add(&btn_run);
+
+        // Progress of a running sequence
+        let box_run = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        root_box.add(&box_run);
+        let progress = gtk::ProgressBar::new();
+        progress.set_show_text(true);
+        progress.set_text(Some(""));
+        box_run.pack_start(&progress, true, true, 0);
+        let btn_abort = gtk::Button::new();
+        btn_abort.set_label("Abort");
+        box_run.add(&btn_abort);
+
+        // Connect Button events
+        connect!(relm, btn_add, connect_clicked(_), Message::AddStep);
+        connect!(relm, btn_remove, connect_clicked(_), Message::RemoveStep);
+        connect!(relm, btn_load, connect_clicked(_), Message::Load);
+        connect!(relm, btn_save, connect_clicked(_), Message::Save);
+        connect!(relm, btn_run, connect_clicked(_), Message::Run);
+        connect!(relm, btn_abort, connect_clicked(_), Message::Stop);
+
+        Self {
+            model,
+            root,
+            store,
+            tree_view,
+            progress,
+            editor,
+        }
+    }
+}
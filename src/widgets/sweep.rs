@@ -0,0 +1,543 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+use crate::widgets::bode::BodePoint;
+
+/// Time between two setpoints of the sweep in ms
+const TICK: u32 = 20;
+
+/// Axes that can be excited
+const AXES: [&str; 2] = ["Roll", "Pitch"];
+const ROLL: usize = 0;
+
+/// Number of frequencies at which the response is estimated
+const RESPONSE_POINTS: usize = 30;
+
+/// Exponential sweep from `f_start` to `f_end` in `duration` s
+#[derive(Clone, Copy)]
+struct Chirp {
+    f_start: f64,
+    f_end: f64,
+    duration: f64,
+}
+
+impl Chirp {
+    fn rate(&self) -> f64 {
+        (self.f_end / self.f_start).ln() / self.duration
+    }
+
+    /// Frequency at time `t` in Hz
+    fn frequency(&self, t: f64) -> f64 {
+        self.f_start * (self.rate() * t).exp()
+    }
+
+    /// Time at which the sweep passes the frequency `f`
+    fn time(&self, f: f64) -> f64 {
+        (f / self.f_start).ln() / self.rate()
+    }
+
+    /// Value of the unit sine at time `t`
+    fn value(&self, t: f64) -> f64 {
+        let phase = 2.0 * std::f64::consts::PI * self.f_start / self.rate()
+            * ((self.rate() * t).exp() - 1.0);
+        phase.sin()
+    }
+}
+
+/// Fourier coefficient of non uniform samples at `frequency`. The mean is removed first.
+fn fourier(samples: &[(f64, f64)], frequency: f64) -> (f64, f64) {
+    let mean = samples.iter().map(|&(_, value)| value).sum::<f64>() / samples.len().max(1) as f64;
+    let omega = 2.0 * std::f64::consts::PI * frequency;
+    let mut re = 0.0;
+    let mut im = 0.0;
+    for pair in samples.windows(2) {
+        let (t, value) = pair[0];
+        let dt = pair[1].0 - t;
+        re += (value - mean) * (omega * t).cos() * dt;
+        im -= (value - mean) * (omega * t).sin() * dt;
+    }
+    (re, im)
+}
+
+/// Estimate gain and phase of the output relative to the input at log spaced frequencies of the sweep.
+/// Each frequency is evaluated on the part of the sweep around it.
+fn frequency_response(
+    input: &[(f64, f64)],
+    output: &[(f64, f64)],
+    chirp: &Chirp,
+) -> Vec<BodePoint> {
+    let ratio = (chirp.f_end / chirp.f_start).powf(1.0 / RESPONSE_POINTS as f64);
+    let mut response: Vec<BodePoint> = Vec::new();
+    for i in 0..RESPONSE_POINTS {
+        let frequency = chirp.f_start * ratio.powf(i as f64 + 0.5);
+        // At least two periods to get a stable estimate
+        let half_window = (chirp.time(frequency * ratio.sqrt())
+            - chirp.time(frequency / ratio.sqrt()))
+        .max(2.0 / frequency)
+            / 2.0;
+        let center = chirp.time(frequency);
+        let window = |samples: &[(f64, f64)]| -> Vec<(f64, f64)> {
+            samples
+                .iter()
+                .copied()
+                .filter(|&(t, _)| (t - center).abs() <= half_window)
+                .collect()
+        };
+        let input = window(input);
+        let output = window(output);
+        if input.len() < 4 || output.len() < 4 {
+            continue;
+        }
+        let (input_re, input_im) = fourier(&input, frequency);
+        let (output_re, output_im) = fourier(&output, frequency);
+        let input_abs = input_re.hypot(input_im);
+        if input_abs <= std::f64::EPSILON {
+            continue;
+        }
+        let gain = output_re.hypot(output_im) / input_abs;
+        let mut phase = (output_im.atan2(output_re) - input_im.atan2(input_re)).to_degrees();
+        // Unwrap the phase to the previous point
+        if let Some(previous) = response.last() {
+            while phase - previous.phase > 180.0 {
+                phase -= 360.0;
+            }
+            while phase - previous.phase < -180.0 {
+                phase += 360.0;
+            }
+        } else if phase > 180.0 {
+            phase -= 360.0;
+        } else if phase < -180.0 {
+            phase += 360.0;
+        }
+        response.push(BodePoint {
+            frequency,
+            gain: 20.0 * gain.max(std::f64::MIN_POSITIVE).log10(),
+            phase,
+        });
+    }
+    response
+}
+
+/// Write the frequency response as CSV
+fn format_response(response: &[BodePoint]) -> String {
+    let mut content = String::from("frequency_hz,gain_db,phase_deg\n");
+    for point in response {
+        content.push_str(&format!(
+            "{},{},{}\n",
+            point.frequency, point.gain, point.phase
+        ));
+    }
+    content
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    /// Start of the running sweep
+    running: Option<(std::time::Instant, Chirp)>,
+    /// Latest roll, pitch and yaw
+    attitude: Option<(f32, f32, f32)>,
+    /// Yaw at the start of the sweep. It is kept during the sweep.
+    yaw: f32,
+    /// Commanded and measured angle over the time since the start
+    input: Vec<(f64, f64)>,
+    output: Vec<(f64, f64)>,
+    response: Vec<BodePoint>,
+    /// Open file selection of an export
+    export_dialog: Option<gtk::FileChooserDialog>,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Attitude(f32, f32, f32),
+    Run,
+    /// Abort by the operator. The copter is levelled with zero throttle.
+    Stop,
+    /// Abort because the control left the scripted mode. The sweep is no longer streamed.
+    Abort,
+    Tick,
+    Export,
+    /// Answer of the file selection, `true` if a file was chosen
+    ExportResponse(bool),
+    SetPoint(copter_com::SetValues),
+    Response(Vec<BodePoint>),
+}
+
+/// Injects a sine sweep on one angle setpoint and estimates the frequency response from the attitude.
+/// The time of the samples is the time of reception, so the delay of the link is part of the phase.
+/// Scripted setpoints skip the rate limit of the limiter, so the recorded input is the sent angle
+/// as long as the amplitude stays within the angle limits.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    axis: gtk::ComboBoxText,
+    amplitude: gtk::Adjustment,
+    throttle: gtk::Adjustment,
+    f_start: gtk::Adjustment,
+    f_end: gtk::Adjustment,
+    duration: gtk::Adjustment,
+    settings: gtk::Grid,
+    progress: gtk::ProgressBar,
+}
+
+impl Widget {
+    fn axis(&self) -> usize {
+        self.axis.get_active().map_or(ROLL, |axis| axis as usize)
+    }
+
+    /// Angle setpoint with the selected axis at `angle`
+    fn setpoint(&self, angle: f64) -> copter_com::SetValues {
+        let angle = angle as f32;
+        let throttle = self.throttle.get_value() as f32;
+        if self.axis() == ROLL {
+            copter_com::SetValues::AngleControl((0.0, angle, self.model.yaw, throttle))
+        } else {
+            copter_com::SetValues::AngleControl((angle, 0.0, self.model.yaw, throttle))
+        }
+    }
+
+    /// Level setpoint with zero throttle that ends a sweep
+    fn neutral(&self) {
+        self.model
+            .relm
+            .stream()
+            .emit(Message::SetPoint(copter_com::SetValues::AngleControl((
+                0.0,
+                0.0,
+                self.model.yaw,
+                0.0,
+            ))));
+    }
+
+    fn set_running(&mut self, running: Option<(std::time::Instant, Chirp)>) {
+        self.settings.set_sensitive(running.is_none());
+        self.model.running = running;
+    }
+
+    fn run(&mut self) {
+        if self.model.running.is_some() {
+            return;
+        }
+        let yaw = match self.model.attitude {
+            Some((_, _, yaw)) => yaw,
+            None => {
+                self.progress.set_text(Some("No telemetry recived"));
+                return;
+            }
+        };
+        let f_start = self.f_start.get_value();
+        let chirp = Chirp {
+            f_start,
+            f_end: self.f_end.get_value().max(f_start * 1.1),
+            duration: self.duration.get_value(),
+        };
+        self.model.yaw = yaw;
+        self.model.input.clear();
+        self.model.output.clear();
+        self.set_running(Some((std::time::Instant::now(), chirp)));
+    }
+
+    fn tick(&mut self) {
+        let (start, chirp) = match self.model.running {
+            Some(running) => running,
+            None => return,
+        };
+        let t = start.elapsed().as_secs_f64();
+        if t >= chirp.duration {
+            // Back to level without throttle and evaluate
+            self.set_running(None);
+            self.neutral();
+            self.model.response = frequency_response(&self.model.input, &self.model.output, &chirp);
+            self.progress.set_fraction(1.0);
+            self.progress.set_text(Some(&format!(
+                "Finished, {} points",
+                self.model.response.len()
+            )));
+            self.model
+                .relm
+                .stream()
+                .emit(Message::Response(self.model.response.clone()));
+            return;
+        }
+
+        let angle = self.amplitude.get_value() * chirp.value(t);
+        self.model.input.push((t, angle));
+        let setpoint = self.setpoint(angle);
+        self.model.relm.stream().emit(Message::SetPoint(setpoint));
+        self.progress.set_fraction(t / chirp.duration);
+        self.progress
+            .set_text(Some(&format!("{:.2} Hz", chirp.frequency(t))));
+    }
+
+    /// Ask for the file of the export. The answer is recived as `ExportResponse`.
+    fn export(&mut self) {
+        if self.model.export_dialog.is_some() {
+            return;
+        }
+        if self.model.response.is_empty() {
+            self.progress
+                .set_text(Some("No frequency response to export"));
+            return;
+        }
+        let window = self
+            .root
+            .get_toplevel()
+            .and_then(|widget| widget.downcast::<gtk::Window>().ok());
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Export Frequency Response"),
+            window.as_ref(),
+            gtk::FileChooserAction::Save,
+            &[
+                ("Cancel", gtk::ResponseType::Cancel),
+                ("Export", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name("frequency_response.csv");
+        connect!(
+            self.model.relm,
+            dialog,
+            connect_response(_, response),
+            Message::ExportResponse(response == gtk::ResponseType::Accept)
+        );
+        dialog.show();
+        self.model.export_dialog = Some(dialog);
+    }
+
+    /// Write the frequency response to the chosen file
+    fn export_response(&mut self, accepted: bool) {
+        let dialog = match self.model.export_dialog.take() {
+            Some(dialog) => dialog,
+            None => return,
+        };
+        if accepted {
+            if let Some(file) = dialog.get_filename() {
+                match std::fs::write(&file, format_response(&self.model.response)) {
+                    Ok(()) => self.progress.set_text(Some("Exported")),
+                    Err(err) => self
+                        .progress
+                        .set_text(Some(&format!("Export failed: {}", err))),
+                }
+            }
+        }
+        dialog.close();
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            running: None,
+            attitude: None,
+            yaw: 0.0,
+            input: Vec::new(),
+            output: Vec::new(),
+            response: Vec::new(),
+            export_dialog: None,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Attitude(roll, pitch, yaw) => {
+                self.model.attitude = Some((roll, pitch, yaw));
+                if let Some((start, _)) = self.model.running {
+                    let value = if self.axis() == ROLL { roll } else { pitch };
+                    self.model
+                        .output
+                        .push((start.elapsed().as_secs_f64(), value as f64));
+                }
+            }
+            Message::Run => self.run(),
+            Message::Stop => {
+                if self.model.running.is_some() {
+                    self.set_running(None);
+                    self.neutral();
+                    self.progress.set_text(Some("Aborted"));
+                }
+            }
+            Message::Abort => {
+                if self.model.running.is_some() {
+                    self.set_running(None);
+                    self.progress.set_text(Some("Aborted"));
+                }
+            }
+            Message::Tick => self.tick(),
+            Message::Export => self.export(),
+            Message::ExportResponse(accepted) => self.export_response(accepted),
+            Message::SetPoint(_) => (),
+            Message::Response(_) => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Frequency Sweep"));
+        let root_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        root.add(&root_box);
+
+        // Settings of the sweep. They are locked while the sweep runs.
+        let settings = gtk::Grid::new();
+        settings.set_column_spacing(5);
+        root_box.add(&settings);
+
+        let label_axis = gtk::Label::new(Some("Axis"));
+        label_axis.set_xalign(0.0);
+        settings.attach(&label_axis, 0, 0, 1, 1);
+        let axis = gtk::ComboBoxText::new();
+        for &name in AXES.iter() {
+            axis.append_text(name);
+        }
+        axis.set_active(Some(ROLL as u32));
+        settings.attach(&axis, 1, 0, 1, 1);
+
+        let add_setting = |row: i32, label: &str, adjustment: &gtk::Adjustment, digits: u32| {
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            settings.attach(&label, 0, row, 1, 1);
+            settings.attach(
+                &gtk::SpinButton::new(Some(adjustment), 0.1, digits),
+                1,
+                row,
+                1,
+                1,
+            );
+        };
+        let amplitude = gtk::Adjustment::new(5.0, 0.5, 20.0, 0.5, 1.0, 0.0);
+        add_setting(1, "Amplitude [°]", &amplitude, 1);
+        let throttle = gtk::Adjustment::new(30.0, 0.0, 100.0, 1.0, 10.0, 0.0);
+        add_setting(2, "Throttle [%]", &throttle, 0);
+        // The highest frequency is limited by the rate of the setpoints
+        let f_start = gtk::Adjustment::new(0.2, 0.05, 5.0, 0.05, 0.5, 0.0);
+        add_setting(3, "Start [Hz]", &f_start, 2);
+        let f_end = gtk::Adjustment::new(5.0, 0.1, 10.0, 0.1, 1.0, 0.0);
+        add_setting(4, "End [Hz]", &f_end, 2);
+        let duration = gtk::Adjustment::new(60.0, 5.0, 600.0, 5.0, 30.0, 0.0);
+        add_setting(5, "Duration [s]", &duration, 0);
+
+        let box_run = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        root_box.add(&box_run);
+        let btn_run = gtk::Button::new();
+        btn_run.set_label("Run Sweep");
+        box_run.add(&btn_run);
+        let btn_abort = gtk::Button::new();
+        btn_abort.set_label("Abort");
+        box_run.add(&btn_abort);
+        let btn_export = gtk::Button::new();
+        btn_export.set_label("Export CSV");
+        box_run.add(&btn_export);
+
+        let progress = gtk::ProgressBar::new();
+        progress.set_show_text(true);
+        progress.set_text(Some(""));
+        root_box.add(&progress);
+
+        connect!(relm, btn_run, connect_clicked(_), Message::Run);
+        connect!(relm, btn_abort, connect_clicked(_), Message::Stop);
+        connect!(relm, btn_export, connect_clicked(_), Message::Export);
+
+        Self {
+            model,
+            root,
+            axis,
+            amplitude,
+            throttle,
+            f_start,
+            f_end,
+            duration,
+            settings,
+            progress,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64::consts::PI;
+
+    /// Samples of `amplitude * sin(2 pi frequency t + phase) + offset` every ms for `duration` s
+    fn sine(
+        amplitude: f64,
+        frequency: f64,
+        phase: f64,
+        offset: f64,
+        duration: f64,
+    ) -> Vec<(f64, f64)> {
+        (0..=(duration * 1000.0) as usize)
+            .map(|i| {
+                let t = i as f64 * 0.001;
+                (
+                    t,
+                    amplitude * (2.0 * PI * frequency * t + phase).sin() + offset,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fourier_of_sine() {
+        // Whole periods, so the coefficient is amplitude * duration / 2
+        let input = sine(1.0, 2.0, 0.0, 0.0, 5.0);
+        let output = sine(0.5, 2.0, -PI / 4.0, 3.0, 5.0);
+        let (input_re, input_im) = fourier(&input, 2.0);
+        let (output_re, output_im) = fourier(&output, 2.0);
+
+        assert!((input_re.hypot(input_im) - 2.5).abs() < 0.01);
+        assert!((output_re.hypot(output_im) - 1.25).abs() < 0.01);
+        let phase = output_im.atan2(output_re) - input_im.atan2(input_re);
+        assert!((phase + PI / 4.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn response_of_gain_and_delay() {
+        let chirp = Chirp {
+            f_start: 0.5,
+            f_end: 5.0,
+            duration: 20.0,
+        };
+        // Output with half the amplitude and 45 ° behind the input at every frequency
+        let samples = |gain: f64, phase: f64| -> Vec<(f64, f64)> {
+            (0..=20_000)
+                .map(|i| {
+                    let t = i as f64 * 0.001;
+                    let angle =
+                        2.0 * PI * chirp.f_start / chirp.rate() * ((chirp.rate() * t).exp() - 1.0);
+                    (t, gain * (angle + phase).sin())
+                })
+                .collect()
+        };
+        let response = frequency_response(&samples(1.0, 0.0), &samples(0.5, -PI / 4.0), &chirp);
+
+        // Single points suffer from the short windows, the mean has to match closely
+        let gain = 20.0 * 0.5f64.log10();
+        assert_eq!(response.len(), RESPONSE_POINTS);
+        for point in &response {
+            assert!((point.gain - gain).abs() < 1.5);
+            assert!((point.phase + 45.0).abs() < 10.0);
+        }
+        let mean = |value: fn(&BodePoint) -> f64| {
+            response.iter().map(value).sum::<f64>() / response.len() as f64
+        };
+        assert!((mean(|point| point.gain) - gain).abs() < 0.2);
+        assert!((mean(|point| point.phase) + 45.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,337 @@
+// Things from relm
+use relm::{connect, Relm};
+use relm_derive::Msg;
+
+// GTK Imports
+use gtk::prelude::*;
+
+/// Time between two updates of the calibration progress in ms
+const TICK: u32 = 100;
+
+/// First line of the trim file
+const FILE_HEADER: &str = "# airframe,roll_trim_deg,pitch_trim_deg";
+
+/// File with the trims of all airframes
+fn trim_file() -> Option<std::path::PathBuf> {
+    glib::get_user_config_dir().map(|dir| dir.join("flight_control").join("trims.csv"))
+}
+
+/// Read the stored airframes with their roll and pitch trims. Invalid lines are skipped.
+fn load_airframes() -> Vec<(String, f32, f32)> {
+    let content = match trim_file().and_then(|file| std::fs::read_to_string(file).ok()) {
+        Some(content) => content,
+        None => return Vec::new(),
+    };
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.rsplitn(3, ',').collect();
+            match fields.as_slice() {
+                [pitch, roll, name] => Some((
+                    name.trim().to_string(),
+                    roll.trim().parse().ok()?,
+                    pitch.trim().parse().ok()?,
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn save_airframes(airframes: &[(String, f32, f32)]) -> Result<(), String> {
+    let file = trim_file().ok_or_else(|| "no config directory".to_string())?;
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    let mut content = String::from(FILE_HEADER);
+    content.push('\n');
+    for (name, roll, pitch) in airframes {
+        content.push_str(&format!("{},{},{}\n", name, roll, pitch));
+    }
+    std::fs::write(file, content).map_err(|err| err.to_string())
+}
+
+/// Setpoint with the trims added to the pitch and roll angles of the angle and stabilize modes.
+/// The copter levels to its own IMU, so level is at the trims. The other modes have no angles.
+fn trim_setpoint(setpoint: &copter_com::SetValues, trims: (f32, f32)) -> copter_com::SetValues {
+    match *setpoint {
+        copter_com::SetValues::AngleControl((pitch, roll, yaw, throttle)) => {
+            copter_com::SetValues::AngleControl((pitch + trims.1, roll + trims.0, yaw, throttle))
+        }
+        copter_com::SetValues::Stabilize((pitch, roll, yaw_rate, throttle)) => {
+            copter_com::SetValues::Stabilize((pitch + trims.1, roll + trims.0, yaw_rate, throttle))
+        }
+        setpoint => setpoint,
+    }
+}
+
+pub struct Model {
+    relm: Relm<Widget>,
+    /// Roll and pitch trims in °
+    trims: (f32, f32),
+    airframes: Vec<(String, f32, f32)>,
+    /// Last setpoint without trims
+    setpoint: Option<copter_com::SetValues>,
+    /// Start of a running calibration with the sum of roll and pitch and the number of samples
+    calibration: Option<(std::time::Instant, f64, f64, u32)>,
+}
+
+#[derive(Msg)]
+pub enum Message {
+    Attitude(u32, f32, f32, f32),
+    SetPoint(Option<copter_com::SetValues>),
+    AirframeChanged,
+    ApplyChanged,
+    Calibrate,
+    Save,
+    Clear,
+    Tick,
+    TrimmedAttitude(u32, f32, f32, f32),
+    Output(Option<copter_com::SetValues>),
+}
+
+/// Level calibration. The trims are removed from the attitude and added to the angle setpoints.
+/// The setpoints are trimmed before the limiter, so a trimmed angle stays within the limits.
+/// copter_com has no message to store trims on the copter, so they are only applied here.
+pub struct Widget {
+    model: Model,
+    root: gtk::Frame,
+    airframe: gtk::ComboBoxText,
+    duration: gtk::Adjustment,
+    apply: gtk::CheckButton,
+    label_trims: gtk::Label,
+    progress: gtk::ProgressBar,
+}
+
+impl Widget {
+    /// Trims that are active
+    fn active_trims(&self) -> (f32, f32) {
+        if self.apply.get_active() {
+            self.model.trims
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    fn set_trims(&mut self, trims: (f32, f32)) {
+        self.model.trims = trims;
+        self.label_trims.set_text(&format!(
+            "Roll Trim: {:.2}°, Pitch Trim: {:.2}°",
+            trims.0, trims.1
+        ));
+        self.send_setpoint();
+    }
+
+    /// Send the last setpoint with the active trims
+    fn send_setpoint(&self) {
+        let trims = self.active_trims();
+        let setpoint = self
+            .model
+            .setpoint
+            .map(|setpoint| trim_setpoint(&setpoint, trims));
+        self.model.relm.stream().emit(Message::Output(setpoint));
+    }
+
+    fn airframe_name(&self) -> Option<String> {
+        self.airframe
+            .get_active_text()
+            .map(|name| name.trim().replace(',', " "))
+            .filter(|name| !name.is_empty())
+    }
+
+    fn airframe_changed(&mut self) {
+        let stored = self.airframe_name().and_then(|name| {
+            self.model
+                .airframes
+                .iter()
+                .find(|airframe| airframe.0 == name)
+                .map(|&(_, roll, pitch)| (roll, pitch))
+        });
+        if let Some(trims) = stored {
+            self.set_trims(trims);
+        }
+    }
+
+    fn save(&mut self) {
+        let name = match self.airframe_name() {
+            Some(name) => name,
+            None => {
+                self.progress.set_text(Some("Enter an airframe name"));
+                return;
+            }
+        };
+        let (roll, pitch) = self.model.trims;
+        match self
+            .model
+            .airframes
+            .iter_mut()
+            .find(|airframe| airframe.0 == name)
+        {
+            Some(airframe) => *airframe = (name, roll, pitch),
+            None => {
+                self.model.airframes.push((name.clone(), roll, pitch));
+                self.airframe.append_text(&name);
+            }
+        }
+        match save_airframes(&self.model.airframes) {
+            Ok(()) => self.progress.set_text(Some("Trims saved")),
+            Err(err) => self
+                .progress
+                .set_text(Some(&format!("Saving failed: {}", err))),
+        }
+    }
+}
+
+impl relm::Update for Widget {
+    type Model = Model;
+    type ModelParam = ();
+    type Msg = Message;
+
+    fn model(relm: &Relm<Self>, _param: Self::ModelParam) -> Self::Model {
+        relm::interval(relm.stream(), TICK, || Message::Tick);
+
+        Model {
+            relm: relm.clone(),
+            trims: (0.0, 0.0),
+            airframes: load_airframes(),
+            setpoint: None,
+            calibration: None,
+        }
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Message::Attitude(timestamp, roll, pitch, yaw) => {
+                if let Some((_, ref mut sum_roll, ref mut sum_pitch, ref mut samples)) =
+                    self.model.calibration
+                {
+                    *sum_roll += roll as f64;
+                    *sum_pitch += pitch as f64;
+                    *samples += 1;
+                }
+                let (roll_trim, pitch_trim) = self.active_trims();
+                self.model.relm.stream().emit(Message::TrimmedAttitude(
+                    timestamp,
+                    roll - roll_trim,
+                    pitch - pitch_trim,
+                    yaw,
+                ));
+            }
+            Message::SetPoint(setpoint) => {
+                self.model.setpoint = setpoint;
+                self.send_setpoint();
+            }
+            Message::AirframeChanged => self.airframe_changed(),
+            Message::ApplyChanged => self.send_setpoint(),
+            Message::Calibrate => {
+                if self.model.calibration.is_none() {
+                    self.model.calibration = Some((std::time::Instant::now(), 0.0, 0.0, 0));
+                    self.progress
+                        .set_text(Some("Keep the copter still on a level surface"));
+                }
+            }
+            Message::Save => self.save(),
+            Message::Clear => {
+                self.set_trims((0.0, 0.0));
+                self.progress.set_text(Some("Trims cleared"));
+            }
+            Message::Tick => {
+                let (start, sum_roll, sum_pitch, samples) = match self.model.calibration {
+                    Some(calibration) => calibration,
+                    None => return,
+                };
+                let fraction = start.elapsed().as_secs_f64() / self.duration.get_value();
+                if fraction < 1.0 {
+                    self.progress.set_fraction(fraction);
+                    return;
+                }
+                self.model.calibration = None;
+                self.progress.set_fraction(1.0);
+                if samples == 0 {
+                    self.progress.set_text(Some("No telemetry recived"));
+                    return;
+                }
+                self.set_trims((
+                    (sum_roll / samples as f64) as f32,
+                    (sum_pitch / samples as f64) as f32,
+                ));
+                self.progress
+                    .set_text(Some(&format!("Calibrated from {} samples", samples)));
+            }
+            Message::TrimmedAttitude(..) => (),
+            Message::Output(_) => (),
+        }
+    }
+}
+
+impl relm::Widget for Widget {
+    type Root = gtk::Frame;
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
+        let root = gtk::Frame::new(Some("Level Calibration"));
+        let grid = gtk::Grid::new();
+        grid.set_column_spacing(5);
+        grid.set_row_spacing(5);
+        root.add(&grid);
+
+        // Airframe selection. A new name is stored on save.
+        let label_airframe = gtk::Label::new(Some("Airframe"));
+        label_airframe.set_xalign(0.0);
+        grid.attach(&label_airframe, 0, 0, 1, 1);
+        let airframe = gtk::ComboBoxText::with_entry();
+        for (name, _, _) in model.airframes.iter() {
+            airframe.append_text(name);
+        }
+        grid.attach(&airframe, 1, 0, 2, 1);
+
+        let label_duration = gtk::Label::new(Some("Duration [s]"));
+        label_duration.set_xalign(0.0);
+        grid.attach(&label_duration, 0, 1, 1, 1);
+        let duration = gtk::Adjustment::new(5.0, 1.0, 60.0, 1.0, 5.0, 0.0);
+        grid.attach(&gtk::SpinButton::new(Some(&duration), 1.0, 0), 1, 1, 1, 1);
+        let btn_calibrate = gtk::Button::new();
+        btn_calibrate.set_label("Calibrate Level");
+        grid.attach(&btn_calibrate, 2, 1, 1, 1);
+
+        let label_trims = gtk::Label::new(Some("Roll Trim: 0.00°, Pitch Trim: 0.00°"));
+        label_trims.set_xalign(0.0);
+        grid.attach(&label_trims, 0, 2, 3, 1);
+
+        let apply = gtk::CheckButton::with_label("Apply Trims");
+        apply.set_active(true);
+        grid.attach(&apply, 0, 3, 1, 1);
+        let btn_save = gtk::Button::new();
+        btn_save.set_label("Save");
+        grid.attach(&btn_save, 1, 3, 1, 1);
+        let btn_clear = gtk::Button::new();
+        btn_clear.set_label("Clear");
+        grid.attach(&btn_clear, 2, 3, 1, 1);
+
+        let progress = gtk::ProgressBar::new();
+        progress.set_show_text(true);
+        progress.set_text(Some(""));
+        grid.attach(&progress, 0, 4, 3, 1);
+
+        connect!(relm, airframe, connect_changed(_), Message::AirframeChanged);
+        connect!(relm, apply, connect_toggled(_), Message::ApplyChanged);
+        connect!(relm, btn_calibrate, connect_clicked(_), Message::Calibrate);
+        connect!(relm, btn_save, connect_clicked(_), Message::Save);
+        connect!(relm, btn_clear, connect_clicked(_), Message::Clear);
+
+        Self {
+            model,
+            root,
+            airframe,
+            duration,
+            apply,
+            label_trims,
+            progress,
+        }
+    }
+}